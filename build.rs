@@ -0,0 +1,8 @@
+fn main() {
+    // The linker leaves calls and addresses padded to 5 bytes unless asked to pack
+    // them, that's about a tenth of the cart. It can't with debug info around, so
+    // only for release builds
+    if std::env::var("PROFILE").as_deref() == Ok("release") {
+        println!("cargo:rustc-link-arg=--compress-relocations");
+    }
+}
@@ -0,0 +1,110 @@
+pub fn pack_bits(values: &[u8], width: u32) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut acc: u32 = 0;
+    let mut acc_bits: u32 = 0;
+    for &value in values {
+        acc = acc << width | (value as u32 & ((1 << width) - 1));
+        acc_bits += width;
+        while acc_bits >= 8 {
+            acc_bits -= 8;
+            bytes.push((acc >> acc_bits) as u8);
+        }
+        acc &= (1 << acc_bits) - 1;
+    }
+    if acc_bits > 0 {
+        bytes.push((acc << (8 - acc_bits)) as u8);
+    }
+    bytes
+}
+
+pub fn unpack_bits(bytes: &[u8], width: u32, count: usize) -> Vec<u8> {
+    let mut values = Vec::with_capacity(count);
+    let mut acc: u32 = 0;
+    let mut acc_bits: u32 = 0;
+    let mut bytes = bytes.iter();
+    while values.len() < count {
+        while acc_bits < width {
+            acc = acc << 8 | *bytes.next().unwrap_or(&0) as u32;
+            acc_bits += 8;
+        }
+        acc_bits -= width;
+        values.push((acc >> acc_bits) as u8 & ((1 << width) - 1) as u8);
+        acc &= (1 << acc_bits) - 1;
+    }
+    values
+}
+
+pub const fn packed_size(count: usize, width: usize) -> usize {
+    (count * width).div_ceil(8)
+}
+
+// Reads past the end of the buffer give zeros, so sections missing from
+// older saves come back with default values
+pub struct ByteReader<'a> {
+    buf: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        ByteReader { buf, position: 0 }
+    }
+
+    // Called for every byte of a save, one out of line copy keeps restore small
+    #[inline(never)]
+    pub fn u8(&mut self) -> u8 {
+        let byte = *self.buf.get(self.position).unwrap_or(&0);
+        self.position += 1;
+        byte
+    }
+
+    // Big endian
+    pub fn u16(&mut self) -> u16 {
+        u16::from_be_bytes([self.u8(), self.u8()])
+    }
+
+    pub fn bytes(&mut self, count: usize) -> &'a [u8] {
+        let start = self.position.min(self.buf.len());
+        let end = (self.position + count).min(self.buf.len());
+        self.position += count;
+        &self.buf[start..end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unpacks_what_was_packed() {
+        let values = [0, 1, 17, 31, 5, 30, 2];
+        let bytes = pack_bits(&values, 5);
+        assert_eq!(bytes.len(), packed_size(values.len(), 5));
+        assert_eq!(unpack_bits(&bytes, 5, values.len()), values);
+    }
+
+    #[test]
+    fn packs_big_endian_and_pads_the_last_byte() {
+        assert_eq!(pack_bits(&[0xa, 0xb, 0xc], 4), [0xab, 0xc0]);
+        assert_eq!(pack_bits(&[1, 2, 3], 6), [0x04, 0x20, 0xc0]);
+    }
+
+    #[test]
+    fn masks_values_to_the_width() {
+        assert_eq!(unpack_bits(&pack_bits(&[0xff, 0x12], 4), 4, 2), [0xf, 0x2]);
+    }
+
+    #[test]
+    fn unpacks_zeros_past_the_end() {
+        assert_eq!(unpack_bits(&[0xab], 4, 4), [0xa, 0xb, 0, 0]);
+    }
+
+    #[test]
+    fn reads_zeros_past_the_end() {
+        let mut reader = ByteReader::new(&[1, 2, 3]);
+        assert_eq!(reader.u16(), 0x0102);
+        assert_eq!(reader.bytes(2), [3]);
+        assert_eq!(reader.u8(), 0);
+        assert_eq!(reader.bytes(2), []);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::wasm4::{TONE_NOISE, TONE_PULSE1, TONE_PULSE2, TONE_TRIANGLE};
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum Channel {
     Pulse1,
@@ -18,6 +20,42 @@ impl Channel {
         .copied()
     }
 
+    pub fn index(&self) -> usize {
+        match self {
+            Channel::Pulse1 => 0,
+            Channel::Pulse2 => 1,
+            Channel::Triangle => 2,
+            Channel::Noise => 3,
+        }
+    }
+
+    pub fn from_index(index: usize) -> Self {
+        match index {
+            1 => Channel::Pulse2,
+            2 => Channel::Triangle,
+            3 => Channel::Noise,
+            _ => Channel::Pulse1,
+        }
+    }
+
+    pub fn to_render(self) -> &'static str {
+        match self {
+            Channel::Pulse1 => "P1",
+            Channel::Pulse2 => "P2",
+            Channel::Triangle => "TR",
+            Channel::Noise => "NS",
+        }
+    }
+
+    pub fn to_flag(self) -> u32 {
+        match self {
+            Channel::Pulse1 => TONE_PULSE1,
+            Channel::Pulse2 => TONE_PULSE2,
+            Channel::Triangle => TONE_TRIANGLE,
+            Channel::Noise => TONE_NOISE,
+        }
+    }
+
     pub fn next(&self) -> Self {
         match self {
             Channel::Pulse1 => Channel::Pulse2,
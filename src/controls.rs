@@ -0,0 +1,327 @@
+use crate::{
+    inputs::{
+        mouse,
+        Button::{Button1, Button2, Down, Left, Right, Up},
+        InputEvent, Inputs,
+    },
+    inputs_mut,
+    navigation::{add_header_handlers, go_to_instrument_list_screen},
+    players::reset_player_handlers,
+    render::controls_input_at,
+    screen::Screen,
+    tracker::{tracker, PlayMode},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Play,
+    Save,
+    AddNote,
+    RemoveNote,
+    PrevScreen,
+    NextScreen,
+    Record,
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::Play,
+        Action::Save,
+        Action::AddNote,
+        Action::RemoveNote,
+        Action::PrevScreen,
+        Action::NextScreen,
+        Action::Record,
+    ];
+
+    pub fn index(&self) -> usize {
+        match self {
+            Action::Play => 0,
+            Action::Save => 1,
+            Action::AddNote => 2,
+            Action::RemoveNote => 3,
+            Action::PrevScreen => 4,
+            Action::NextScreen => 5,
+            Action::Record => 6,
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            Action::Play => Action::Save,
+            Action::Save => Action::AddNote,
+            Action::AddNote => Action::RemoveNote,
+            Action::RemoveNote => Action::PrevScreen,
+            Action::PrevScreen => Action::NextScreen,
+            Action::NextScreen => Action::Record,
+            &record @ Action::Record => record,
+        }
+    }
+
+    pub fn prev(&self) -> Self {
+        match self {
+            &play @ Action::Play => play,
+            Action::Save => Action::Play,
+            Action::AddNote => Action::Save,
+            Action::RemoveNote => Action::AddNote,
+            Action::PrevScreen => Action::RemoveNote,
+            Action::NextScreen => Action::PrevScreen,
+            Action::Record => Action::NextScreen,
+        }
+    }
+
+    pub fn to_render(self) -> &'static str {
+        match self {
+            Action::Play => "Play",
+            Action::Save => "Save",
+            Action::AddNote => "Add note",
+            Action::RemoveNote => "Remove note",
+            Action::PrevScreen => "Prev screen",
+            Action::NextScreen => "Next screen",
+            Action::Record => "Record",
+        }
+    }
+
+    // Label in the pattern screen help, at most four characters
+    pub fn to_help(self) -> &'static str {
+        match self {
+            Action::Play => "play",
+            Action::Save => "save",
+            Action::AddNote => "add",
+            Action::RemoveNote => "rm",
+            Action::PrevScreen => "prev",
+            Action::NextScreen => "next",
+            Action::Record => "rec",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ControlsInput {
+    Binding(Action),
+    Preview,
+}
+
+impl ControlsInput {
+    pub fn next(&self) -> Self {
+        match self {
+            ControlsInput::Binding(Action::Record) => ControlsInput::Preview,
+            ControlsInput::Binding(action) => ControlsInput::Binding(action.next()),
+            &preview @ ControlsInput::Preview => preview,
+        }
+    }
+
+    pub fn prev(&self) -> Self {
+        match self {
+            ControlsInput::Binding(action) => ControlsInput::Binding(action.prev()),
+            ControlsInput::Preview => ControlsInput::Binding(Action::Record),
+        }
+    }
+}
+
+// Directions and B1+direction stay with the screens, actions take the rest
+const BINDABLE_EVENTS: [InputEvent; 11] = [
+    InputEvent::Combo(Button2, Up),
+    InputEvent::Combo(Button2, Down),
+    InputEvent::Combo(Button2, Left),
+    InputEvent::Combo(Button2, Right),
+    InputEvent::Combo(Button1, Button2),
+    InputEvent::Combo(Button2, Button1),
+    InputEvent::Press(Button1),
+    InputEvent::DoublePress(Button1),
+    InputEvent::LongPress(Button1),
+    InputEvent::DoublePress(Button2),
+    InputEvent::LongPress(Button2),
+];
+
+// Indexes into BINDABLE_EVENTS by action
+const DEFAULT_BINDINGS: [u8; 7] = [1, 0, 6, 7, 2, 3, 5];
+
+// Every action has its own event, rebinding swaps events between actions
+#[derive(Clone, Copy)]
+pub struct Bindings {
+    events: [u8; 7],
+}
+
+impl Bindings {
+    pub const fn new() -> Self {
+        Bindings {
+            events: DEFAULT_BINDINGS,
+        }
+    }
+
+    pub fn event(&self, action: Action) -> InputEvent {
+        BINDABLE_EVENTS[self.events[action.index()] as usize]
+    }
+
+    pub fn rebind(&mut self, action: Action, forward: bool) {
+        let count = BINDABLE_EVENTS.len() as u8;
+        let old = self.events[action.index()];
+        let new = if forward {
+            (old + 1) % count
+        } else {
+            (old + count - 1) % count
+        };
+        if let Some(other) = self.events.iter().position(|&a| a == new) {
+            self.events[other] = old;
+        }
+        self.events[action.index()] = new;
+    }
+
+    // Two actions per byte, event index + 1 so zeroed nibbles give the defaults
+    pub fn to_bytes(self) -> [u8; 4] {
+        let mut bytes = [0; 4];
+        for (index, event) in self.events.iter().enumerate() {
+            bytes[index / 2] |= (event + 1) << (index % 2 * 4);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: [u8; 4]) -> Self {
+        let mut bindings = Bindings::new();
+        for (index, event) in bindings.events.iter_mut().enumerate() {
+            match bytes[index / 2] >> (index % 2 * 4) & 0x0f {
+                0 => {}
+                nibble => *event = nibble - 1,
+            }
+        }
+        let valid = bindings.events.iter().enumerate().all(|(index, &event)| {
+            (event as usize) < BINDABLE_EVENTS.len() && !bindings.events[..index].contains(&event)
+        });
+        if valid {
+            bindings
+        } else {
+            Bindings::new()
+        }
+    }
+}
+
+fn on_button_down_press(_inputs: &Inputs) {
+    unsafe { tracker().controls_focus_next() }
+}
+
+fn on_button_up_press(_inputs: &Inputs) {
+    unsafe { tracker().controls_focus_prev() }
+}
+
+unsafe fn update_focused_value(forward: bool) {
+    match tracker().controls_focus() {
+        ControlsInput::Binding(action) => {
+            tracker().bindings_mut().rebind(action, forward);
+            // The actions of this screen have to follow the new table
+            inputs_mut()[0].unlisten();
+            add_controls_screen_handlers(&mut inputs_mut()[0]);
+            reset_player_handlers();
+        }
+        ControlsInput::Preview => tracker().toggle_preview(),
+    }
+}
+
+fn on_button_1_left_press(_inputs: &Inputs) {
+    unsafe { update_focused_value(false) }
+}
+
+fn on_button_1_right_press(_inputs: &Inputs) {
+    unsafe { update_focused_value(true) }
+}
+
+fn on_mouse_click(_inputs: &Inputs) {
+    let (_, y) = mouse().position();
+    if let Some(input) = controls_input_at(y) {
+        unsafe { tracker().set_controls_focus(input) }
+    }
+}
+
+fn on_mouse_drag(_inputs: &Inputs) {
+    let steps = mouse().drag_steps();
+    unsafe {
+        for _ in 0..steps.unsigned_abs() {
+            update_focused_value(steps > 0);
+        }
+    }
+}
+
+fn on_mouse_wheel(_inputs: &Inputs) {
+    unsafe {
+        for _ in 0..mouse().wheel_steps() {
+            tracker().controls_focus_next()
+        }
+        for _ in mouse().wheel_steps()..0 {
+            tracker().controls_focus_prev()
+        }
+    }
+}
+
+fn on_play(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().toggle_play(PlayMode::Pattern)
+        }
+    }
+}
+
+fn on_save(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().persist();
+        }
+    }
+}
+
+fn on_prev_screen(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            go_to_instrument_list_screen(Screen::Controls);
+        }
+    }
+}
+
+pub fn add_controls_screen_handlers(inputs: &mut Inputs) {
+    let bindings = unsafe { *tracker().bindings() };
+    inputs
+        .listen(InputEvent::Press(Down), on_button_down_press)
+        .listen(InputEvent::Press(Up), on_button_up_press)
+        .listen(InputEvent::Combo(Button1, Left), on_button_1_left_press)
+        .listen(InputEvent::Combo(Button1, Right), on_button_1_right_press)
+        .listen(bindings.event(Action::Play), on_play)
+        .listen(bindings.event(Action::Save), on_save)
+        .listen(bindings.event(Action::PrevScreen), on_prev_screen)
+        .listen(InputEvent::MouseClick, on_mouse_click)
+        .listen(InputEvent::MouseDrag, on_mouse_drag)
+        .listen(InputEvent::MouseWheel, on_mouse_wheel);
+    add_header_handlers(inputs);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swaps_events_when_rebinding_to_a_taken_one() {
+        let mut bindings = Bindings::new();
+        let save = bindings.event(Action::Save);
+        let play = bindings.event(Action::Play);
+        bindings.rebind(Action::Save, true);
+        assert!(bindings.event(Action::Save) == play);
+        assert!(bindings.event(Action::Play) == save);
+    }
+
+    #[test]
+    fn restores_what_was_saved() {
+        let mut bindings = Bindings::new();
+        bindings.rebind(Action::Record, false);
+        bindings.rebind(Action::AddNote, true);
+        let bytes = bindings.to_bytes();
+        assert_eq!(Bindings::from_bytes(bytes).events, bindings.events);
+    }
+
+    #[test]
+    fn restores_the_defaults_from_zeros() {
+        assert_eq!(Bindings::from_bytes([0; 4]).events, DEFAULT_BINDINGS);
+    }
+
+    #[test]
+    fn restores_the_defaults_from_clashing_bindings() {
+        assert_eq!(Bindings::from_bytes([0x11; 4]).events, DEFAULT_BINDINGS);
+    }
+}
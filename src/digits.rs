@@ -0,0 +1,32 @@
+// Numbers as text without core::fmt, the formatting machinery alone takes a
+// tenth of the cart
+
+// Upper case digits in `base`, zero padded to at least `width`
+pub fn digits(value: u32, base: u32, width: usize) -> String {
+    let mut bytes = vec![];
+    let mut rest = value;
+    while rest > 0 || bytes.len() < width.max(1) {
+        let digit = (rest % base) as u8;
+        bytes.push(if digit < 10 {
+            b'0' + digit
+        } else {
+            b'A' + digit - 10
+        });
+        rest /= base;
+    }
+    bytes.iter().rev().map(|&a| a as char).collect()
+}
+
+pub fn hex(value: u32, width: usize) -> String {
+    digits(value, 16, width)
+}
+
+pub fn decimal(value: u32, width: usize) -> String {
+    digits(value, 10, width)
+}
+
+// Always with a sign, zero is "+0"
+pub fn signed_decimal(value: i32) -> String {
+    let sign = if value < 0 { "-" } else { "+" };
+    sign.to_string() + &decimal(value.unsigned_abs(), 1)
+}
@@ -0,0 +1,376 @@
+use crate::notes::NOTE_C3_INDEX;
+
+// Keys of a drum kit, from C-3 up
+pub const DRUM_KIT_SIZE: usize = 0x10;
+
+pub const DEFAULT_DRUM_KIT: [u8; DRUM_KIT_SIZE] = [
+    0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9, 0xa, 0xb, 0xc, 0xd, 0xe, 0xf,
+];
+
+#[derive(Clone, Copy)]
+pub struct Drum {
+    name: [u8; 3],
+    frequency: u16,
+    sweep_to: u16,
+    attack: u8,
+    decay: u8,
+    sustain: u8,
+    release: u8,
+    volume: u8,
+    peak: u8,
+}
+
+impl Drum {
+    // The drum names are ASCII literals
+    pub fn name(&self) -> &str {
+        unsafe { std::str::from_utf8_unchecked(&self.name) }
+    }
+
+    pub fn frequency(&self) -> u16 {
+        self.frequency
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn release(&self) -> u8 {
+        self.release
+    }
+
+    pub fn held_frames(&self) -> u32 {
+        self.attack as u32 + self.decay as u32 + self.sustain as u32
+    }
+
+    pub fn get_frequency(&self) -> u32 {
+        self.frequency as u32 | (self.sweep_to as u32) << 16
+    }
+
+    pub fn get_duration(&self) -> u32 {
+        (self.attack as u32) << 24
+            | (self.decay as u32) << 16
+            | self.sustain as u32
+            | (self.release as u32) << 8
+    }
+
+    pub fn get_volume(&self) -> u32 {
+        (self.peak as u32) << 8 | self.volume as u32
+    }
+}
+
+// Kit changes are in sixteenths, from a sixteenth to twice the drum's value
+const MIN_KIT_STEP: i8 = -15;
+const MAX_KIT_STEP: i8 = 16;
+const MAX_KIT_VOLUME: u8 = 100;
+
+fn kit_scale(value: u32, step: i8) -> u32 {
+    value * (16 + step as i32) as u32 / 16
+}
+
+// Changes a kit makes to every drum it plays, so a kit can sound lower,
+// longer or quieter than the drums it picks
+#[derive(Clone, Copy, PartialEq)]
+pub struct KitParams {
+    frequency: i8,
+    sweep: i8,
+    envelope: i8,
+    volume: u8,
+}
+
+impl KitParams {
+    pub const fn new() -> Self {
+        KitParams {
+            frequency: 0,
+            sweep: 0,
+            envelope: 0,
+            volume: MAX_KIT_VOLUME,
+        }
+    }
+
+    pub fn frequency(&self) -> i8 {
+        self.frequency
+    }
+
+    pub fn sweep(&self) -> i8 {
+        self.sweep
+    }
+
+    pub fn envelope(&self) -> i8 {
+        self.envelope
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn update_frequency<F>(&mut self, f: F)
+    where
+        F: FnOnce(i8) -> i8,
+    {
+        self.frequency = f(self.frequency).clamp(MIN_KIT_STEP, MAX_KIT_STEP)
+    }
+
+    pub fn update_sweep<F>(&mut self, f: F)
+    where
+        F: FnOnce(i8) -> i8,
+    {
+        self.sweep = f(self.sweep).clamp(MIN_KIT_STEP, MAX_KIT_STEP)
+    }
+
+    pub fn update_envelope<F>(&mut self, f: F)
+    where
+        F: FnOnce(i8) -> i8,
+    {
+        self.envelope = f(self.envelope).clamp(MIN_KIT_STEP, MAX_KIT_STEP)
+    }
+
+    pub fn update_volume<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.volume = f(self.volume).min(MAX_KIT_VOLUME)
+    }
+
+    // The sweep moves the end of the drum's slide, drums without one start
+    // sliding from their frequency
+    pub fn apply(&self, drum: &Drum) -> Drum {
+        let length = |frames: u8| kit_scale(frames as u32, self.envelope).min(255) as u8;
+        let frequency = kit_scale(drum.frequency as u32, self.frequency);
+        let sweep_from = match drum.sweep_to {
+            0 => drum.frequency,
+            sweep_to => sweep_to,
+        };
+        let sweep_to = match (drum.sweep_to, self.sweep) {
+            (0, 0) => 0,
+            _ => kit_scale(kit_scale(sweep_from as u32, self.frequency), self.sweep),
+        };
+        Drum {
+            frequency: frequency.min(u16::MAX as u32) as u16,
+            sweep_to: sweep_to.min(u16::MAX as u32) as u16,
+            attack: length(drum.attack),
+            decay: length(drum.decay),
+            sustain: length(drum.sustain),
+            release: length(drum.release),
+            volume: (drum.volume as u32 * self.volume as u32 / 100) as u8,
+            peak: (drum.peak as u32 * self.volume as u32 / 100) as u8,
+            ..*drum
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; 4] {
+        [
+            self.frequency as u8,
+            self.sweep as u8,
+            self.envelope as u8,
+            self.volume,
+        ]
+    }
+
+    pub fn from_bytes(bytes: [u8; 4]) -> Self {
+        let mut params = KitParams::new();
+        params.update_frequency(|_| bytes[0] as i8);
+        params.update_sweep(|_| bytes[1] as i8);
+        params.update_envelope(|_| bytes[2] as i8);
+        params.update_volume(|_| bytes[3]);
+        params
+    }
+}
+
+impl Default for KitParams {
+    fn default() -> Self {
+        KitParams::new()
+    }
+}
+
+// Drums a kit key can play, a kit instrument on the noise channel picks one per key
+pub const DRUM_COUNT: usize = 0x10;
+
+pub static DRUMS: [Drum; DRUM_COUNT] = [
+    Drum {
+        name: *b"KCK",
+        frequency: 180,
+        sweep_to: 40,
+        attack: 0,
+        decay: 6,
+        sustain: 0,
+        release: 4,
+        volume: 0x64,
+        peak: 0x64,
+    },
+    Drum {
+        name: *b"KC2",
+        frequency: 240,
+        sweep_to: 60,
+        attack: 0,
+        decay: 4,
+        sustain: 0,
+        release: 8,
+        volume: 0x5a,
+        peak: 0x64,
+    },
+    Drum {
+        name: *b"SNR",
+        frequency: 1200,
+        sweep_to: 700,
+        attack: 0,
+        decay: 6,
+        sustain: 0,
+        release: 8,
+        volume: 0x46,
+        peak: 0x64,
+    },
+    Drum {
+        name: *b"SN2",
+        frequency: 1800,
+        sweep_to: 1000,
+        attack: 0,
+        decay: 4,
+        sustain: 2,
+        release: 10,
+        volume: 0x3c,
+        peak: 0x5a,
+    },
+    Drum {
+        name: *b"CLP",
+        frequency: 1500,
+        sweep_to: 1500,
+        attack: 0,
+        decay: 2,
+        sustain: 2,
+        release: 6,
+        volume: 0x3c,
+        peak: 0x50,
+    },
+    Drum {
+        name: *b"RIM",
+        frequency: 2600,
+        sweep_to: 2600,
+        attack: 0,
+        decay: 1,
+        sustain: 0,
+        release: 2,
+        volume: 0x3c,
+        peak: 0x50,
+    },
+    Drum {
+        name: *b"HAT",
+        frequency: 6000,
+        sweep_to: 6000,
+        attack: 0,
+        decay: 2,
+        sustain: 0,
+        release: 3,
+        volume: 0x28,
+        peak: 0x3c,
+    },
+    Drum {
+        name: *b"OHT",
+        frequency: 6000,
+        sweep_to: 6000,
+        attack: 0,
+        decay: 4,
+        sustain: 2,
+        release: 12,
+        volume: 0x28,
+        peak: 0x3c,
+    },
+    Drum {
+        name: *b"PHT",
+        frequency: 5000,
+        sweep_to: 5000,
+        attack: 0,
+        decay: 1,
+        sustain: 0,
+        release: 2,
+        volume: 0x1e,
+        peak: 0x32,
+    },
+    Drum {
+        name: *b"TMH",
+        frequency: 600,
+        sweep_to: 400,
+        attack: 0,
+        decay: 8,
+        sustain: 0,
+        release: 6,
+        volume: 0x50,
+        peak: 0x64,
+    },
+    Drum {
+        name: *b"TMM",
+        frequency: 450,
+        sweep_to: 300,
+        attack: 0,
+        decay: 8,
+        sustain: 0,
+        release: 6,
+        volume: 0x50,
+        peak: 0x64,
+    },
+    Drum {
+        name: *b"TML",
+        frequency: 320,
+        sweep_to: 200,
+        attack: 0,
+        decay: 10,
+        sustain: 0,
+        release: 8,
+        volume: 0x50,
+        peak: 0x64,
+    },
+    Drum {
+        name: *b"CRS",
+        frequency: 4000,
+        sweep_to: 3000,
+        attack: 0,
+        decay: 10,
+        sustain: 10,
+        release: 30,
+        volume: 0x1e,
+        peak: 0x50,
+    },
+    Drum {
+        name: *b"RID",
+        frequency: 7000,
+        sweep_to: 6500,
+        attack: 0,
+        decay: 6,
+        sustain: 8,
+        release: 20,
+        volume: 0x1e,
+        peak: 0x3c,
+    },
+    Drum {
+        name: *b"SHK",
+        frequency: 8000,
+        sweep_to: 8000,
+        attack: 2,
+        decay: 2,
+        sustain: 0,
+        release: 2,
+        volume: 0x1e,
+        peak: 0x28,
+    },
+    Drum {
+        name: *b"ZAP",
+        frequency: 3000,
+        sweep_to: 200,
+        attack: 0,
+        decay: 8,
+        sustain: 0,
+        release: 4,
+        volume: 0x3c,
+        peak: 0x50,
+    },
+];
+
+pub fn drum(index: usize) -> &'static Drum {
+    &DRUMS[index % DRUM_COUNT]
+}
+
+// Kit key of a note, notes outside the kit are played as pitched noise
+pub fn kit_key(note_index: usize) -> Option<usize> {
+    note_index
+        .checked_sub(NOTE_C3_INDEX)
+        .filter(|&key| key < DRUM_KIT_SIZE)
+}
@@ -0,0 +1,130 @@
+use crate::channel::Channel;
+
+const MIN_DELAY: u8 = 1;
+const MAX_DELAY: u8 = 0x0f;
+const DEFAULT_DELAY: u8 = 3;
+const MAX_VOLUME: u8 = 100;
+const DEFAULT_VOLUME: u8 = 50;
+
+// Notes of the source channel are replayed on the target channel `delay`
+// ticks later at `volume` percent
+#[derive(Clone, Copy)]
+pub struct Echo {
+    source: Option<Channel>,
+    target: Channel,
+    delay: u8,
+    volume: u8,
+}
+
+impl Echo {
+    pub const fn new() -> Self {
+        Echo {
+            source: None,
+            target: Channel::Noise,
+            delay: DEFAULT_DELAY,
+            volume: DEFAULT_VOLUME,
+        }
+    }
+
+    pub fn source(&self) -> Option<Channel> {
+        self.source
+    }
+
+    pub fn target(&self) -> Channel {
+        self.target
+    }
+
+    pub fn delay(&self) -> u8 {
+        self.delay
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    // Off comes before the first channel
+    pub fn next_source(&mut self) {
+        self.source = match self.source {
+            None => Some(Channel::Pulse1),
+            Some(channel) => Some(channel.next()),
+        }
+    }
+
+    pub fn prev_source(&mut self) {
+        self.source = match self.source {
+            None | Some(Channel::Pulse1) => None,
+            Some(channel) => Some(channel.prev()),
+        }
+    }
+
+    pub fn update_target<F>(&mut self, f: F)
+    where
+        F: FnOnce(Channel) -> Channel,
+    {
+        self.target = f(self.target)
+    }
+
+    pub fn update_delay<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.delay = f(self.delay).clamp(MIN_DELAY, MAX_DELAY)
+    }
+
+    pub fn update_volume<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.volume = f(self.volume).min(MAX_VOLUME)
+    }
+
+    pub fn to_bytes(self) -> [u8; 3] {
+        let source = self.source.map_or(0, |a| a.index() as u8 + 1);
+        [
+            source | (self.target.index() as u8) << 3,
+            self.delay,
+            self.volume,
+        ]
+    }
+
+    // Zeroed bytes, as read from older saves, give the default echo
+    pub fn from_bytes(bytes: [u8; 3]) -> Self {
+        let mut echo = Echo::new();
+        if bytes == [0; 3] {
+            return echo;
+        }
+        echo.source = match bytes[0] & 0b111 {
+            0 => None,
+            source => Some(Channel::from_index(source as usize - 1)),
+        };
+        echo.target = Channel::from_index((bytes[0] >> 3 & 0b11) as usize);
+        echo.update_delay(|_| bytes[1]);
+        echo.update_volume(|_| bytes[2]);
+        echo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_what_was_saved() {
+        let mut echo = Echo::new();
+        echo.next_source();
+        echo.next_source();
+        echo.update_target(|_| Channel::Triangle);
+        echo.update_delay(|_| 7);
+        echo.update_volume(|_| 30);
+        let restored = Echo::from_bytes(echo.to_bytes());
+        assert!(restored.source() == Some(Channel::Pulse2));
+        assert!(restored.target() == Channel::Triangle);
+        assert_eq!(restored.delay(), 7);
+        assert_eq!(restored.volume(), 30);
+    }
+
+    #[test]
+    fn restores_the_default_from_zeros() {
+        assert_eq!(Echo::from_bytes([0; 3]).to_bytes(), Echo::new().to_bytes());
+    }
+}
@@ -1,53 +1,285 @@
-use std::{collections::HashMap, time::Duration};
+use std::ptr::addr_of;
 
-use crate::{
-    wasm4::{BUTTON_1, BUTTON_2, BUTTON_DOWN, BUTTON_LEFT, BUTTON_RIGHT, BUTTON_UP, GAMEPAD1},
-    wtime::{FromFrames, Winstant},
+use crate::wasm4::{
+    BUTTON_1, BUTTON_2, BUTTON_DOWN, BUTTON_LEFT, BUTTON_RIGHT, BUTTON_UP, GAMEPAD1, MOUSE_BUTTONS,
+    MOUSE_LEFT, MOUSE_MIDDLE, MOUSE_RIGHT, MOUSE_X, MOUSE_Y,
 };
 
-#[derive(Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+pub enum Button {
+    Button1,
+    Button2,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Button {
+    const ALL: [Button; 6] = [
+        Button::Button1,
+        Button::Button2,
+        Button::Up,
+        Button::Down,
+        Button::Left,
+        Button::Right,
+    ];
+
+    fn index(&self) -> usize {
+        match self {
+            Button::Button1 => 0,
+            Button::Button2 => 1,
+            Button::Up => 2,
+            Button::Down => 3,
+            Button::Left => 4,
+            Button::Right => 5,
+        }
+    }
+
+    fn mask(&self) -> u8 {
+        match self {
+            Button::Button1 => BUTTON_1,
+            Button::Button2 => BUTTON_2,
+            Button::Up => BUTTON_UP,
+            Button::Down => BUTTON_DOWN,
+            Button::Left => BUTTON_LEFT,
+            Button::Right => BUTTON_RIGHT,
+        }
+    }
+
+    // Buttons that are held to change what the other buttons do
+    fn is_modifier(&self) -> bool {
+        matches!(self, Button::Button1 | Button::Button2)
+    }
+
+    // Glyph of the button in the WASM-4 font
+    pub fn to_render(self) -> u8 {
+        match self {
+            Button::Button1 => b'\x80',
+            Button::Button2 => b'\x81',
+            Button::Left => b'\x84',
+            Button::Right => b'\x85',
+            Button::Up => b'\x86',
+            Button::Down => b'\x87',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
 pub enum InputEvent {
-    Button1Press,
-    Button1DoublePress,
-    Button2Press,
-    ButtonUpPress,
-    ButtonDownPress,
-    ButtonLeftPress,
-    ButtonRightPress,
+    // Fires again while a direction is held, see is_repeat
+    Press(Button),
+    DoublePress(Button),
+    LongPress(Button),
+    // The second button pressed while the first one is held, e.g. Hold(Button2)+Up
+    Combo(Button, Button),
+    MouseClick,
+    MouseRightClick,
+    MouseDrag,
+    MouseWheel,
+}
+
+impl InputEvent {
+    // At most three glyphs, mouse events have none
+    pub fn to_render(self) -> Vec<u8> {
+        match self {
+            InputEvent::Press(button) => vec![button.to_render()],
+            InputEvent::DoublePress(button) => vec![button.to_render(), button.to_render()],
+            InputEvent::LongPress(button) => vec![button.to_render(), b'_'],
+            InputEvent::Combo(hold, button) => vec![hold.to_render(), b'+', button.to_render()],
+            InputEvent::MouseClick
+            | InputEvent::MouseRightClick
+            | InputEvent::MouseDrag
+            | InputEvent::MouseWheel => vec![],
+        }
+    }
+}
+
+// Frames a button is held before it starts repeating
+const REPEAT_DELAY: u32 = 15;
+// Frames between the first repeats, shortened by one every REPEAT_ACCELERATION repeats
+const REPEAT_INTERVAL: u32 = 6;
+const REPEAT_ACCELERATION: u32 = 4;
+// A second press this many frames after a release is a double press
+const DOUBLE_PRESS_FRAMES: u32 = 12;
+// Holding a button this long without using it in a combo is a long press
+const LONG_PRESS_FRAMES: u32 = 30;
+
+// Pixels of horizontal left-button drag per value step
+const DRAG_STEP: i32 = 4;
+// WASM-4 has no wheel, a vertical middle-button drag stands in for it
+const WHEEL_STEP: i32 = 8;
+
+#[derive(Clone, Copy)]
+struct ButtonState {
+    held_frames: u32,
+    released_frames: u32,
+    next_repeat: u32,
+    repeats: u32,
+    double_press: bool,
+    // Held while another button was pressed, which rules out a long press
+    combined: bool,
+    // What happened to the button on the current frame
+    pressed: bool,
+    repeated: bool,
+    released: bool,
+    long_pressed: bool,
+}
+
+impl ButtonState {
+    const fn new() -> Self {
+        ButtonState {
+            held_frames: 0,
+            released_frames: u32::MAX,
+            next_repeat: 0,
+            repeats: 0,
+            double_press: false,
+            combined: false,
+            pressed: false,
+            repeated: false,
+            released: false,
+            long_pressed: false,
+        }
+    }
+
+    fn update(&mut self, down: bool, repeats: bool) {
+        self.pressed = down && self.held_frames == 0;
+        self.released = !down && self.held_frames != 0;
+        self.repeated = false;
+        if self.pressed {
+            self.double_press = self.released_frames <= DOUBLE_PRESS_FRAMES;
+            self.next_repeat = REPEAT_DELAY;
+            self.repeats = 0;
+            self.combined = false;
+        }
+        if down {
+            self.held_frames += 1;
+            if repeats && self.held_frames > self.next_repeat {
+                self.repeated = true;
+                self.repeats += 1;
+                self.next_repeat += REPEAT_INTERVAL
+                    .saturating_sub(self.repeats / REPEAT_ACCELERATION)
+                    .max(1);
+            }
+        } else {
+            self.released_frames = if self.released {
+                0
+            } else {
+                self.released_frames.saturating_add(1)
+            };
+            self.held_frames = 0;
+        }
+        self.long_pressed = self.held_frames == LONG_PRESS_FRAMES && !self.combined;
+    }
+
+    fn is_held(&self) -> bool {
+        self.held_frames != 0
+    }
+
+    fn is_pressed_or_repeated(&self) -> bool {
+        self.pressed || self.repeated
+    }
+}
+
+// There is one mouse for all the gamepads, it's ticked once per frame before them
+pub struct Mouse {
+    buttons: u8,
+    pressed: u8,
+    x: i32,
+    y: i32,
+    drag_distance: i32,
+    wheel_distance: i32,
+    drag_steps: i32,
+    wheel_steps: i32,
+}
+
+pub static mut MOUSE: Mouse = Mouse::new();
+
+pub fn mouse() -> &'static Mouse {
+    unsafe { &*addr_of!(MOUSE) }
+}
+
+impl Mouse {
+    const fn new() -> Self {
+        Mouse {
+            buttons: 0,
+            pressed: 0,
+            x: 0,
+            y: 0,
+            drag_distance: 0,
+            wheel_distance: 0,
+            drag_steps: 0,
+            wheel_steps: 0,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        let (buttons, x, y) = unsafe { (*MOUSE_BUTTONS, *MOUSE_X, *MOUSE_Y) };
+        let (x, y) = (i32::from(x), i32::from(y));
+        self.pressed = buttons & !self.buttons;
+        let held = buttons & self.buttons;
+
+        self.drag_distance = if held & MOUSE_LEFT != 0 {
+            self.drag_distance + x - self.x
+        } else {
+            0
+        };
+        self.drag_steps = self.drag_distance / DRAG_STEP;
+        self.drag_distance %= DRAG_STEP;
+
+        self.wheel_distance = if held & MOUSE_MIDDLE != 0 {
+            self.wheel_distance + y - self.y
+        } else {
+            0
+        };
+        self.wheel_steps = self.wheel_distance / WHEEL_STEP;
+        self.wheel_distance %= WHEEL_STEP;
+
+        self.buttons = buttons;
+        self.x = x;
+        self.y = y;
+    }
+
+    pub fn position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    // Value steps dragged to the right since the last frame, negative to the left
+    pub fn drag_steps(&self) -> i32 {
+        self.drag_steps
+    }
+
+    // Wheel notches since the last frame, negative when scrolling up
+    pub fn wheel_steps(&self) -> i32 {
+        self.wheel_steps
+    }
 }
 
+// Plain functions, a boxed closure per handler doesn't fit into the cart
 struct StoredHandler {
-    handler: Box<dyn Fn(&Inputs)>,
+    handler: fn(&Inputs),
     event: InputEvent,
 }
 
 pub struct Inputs {
-    initialized: bool,
     handlers: Vec<StoredHandler>,
-    last_fire: Option<HashMap<InputEvent, Winstant>>,
-    double_press_activated: bool,
+    gamepad: *const u8,
+    buttons: [ButtonState; 6],
+    repeating: bool,
 }
 
 impl Inputs {
-    pub const fn new() -> Self {
+    pub const fn new(gamepad: *const u8) -> Self {
         Inputs {
-            initialized: false,
             handlers: vec![],
-            last_fire: None,
-            double_press_activated: false,
+            gamepad,
+            buttons: [ButtonState::new(); 6],
+            repeating: false,
         }
     }
 
-    fn init(&mut self) {
-        self.last_fire = Some(HashMap::new());
-        self.initialized = true;
-    }
-
-    pub fn listen(&mut self, event: InputEvent, handler: impl Fn(&Self) + 'static) -> &mut Self {
-        self.handlers.push(StoredHandler {
-            handler: Box::new(handler),
-            event,
-        });
+    pub fn listen(&mut self, event: InputEvent, handler: fn(&Self)) -> &mut Self {
+        self.handlers.push(StoredHandler { handler, event });
         self
     }
 
@@ -55,73 +287,97 @@ impl Inputs {
         self.handlers.clear();
     }
 
-    fn get_last_fire(&self, event: InputEvent) -> Option<&Winstant> {
-        let map = self.last_fire.as_ref()?;
-        map.get(&event)
+    fn state(&self, button: Button) -> &ButtonState {
+        &self.buttons[button.index()]
+    }
+
+    fn is_modifier_held(&self, button: Button) -> bool {
+        Button::ALL
+            .into_iter()
+            .any(|a| a != button && a.is_modifier() && self.state(a).is_held())
+    }
+
+    fn fires(&self, event: &InputEvent, double_pressable: &[bool; 6]) -> bool {
+        match *event {
+            // A double press replaces the second press when somebody listens for it
+            InputEvent::Press(button) => {
+                let state = self.state(button);
+                state.is_pressed_or_repeated()
+                    && !self.is_modifier_held(button)
+                    && !(state.double_press && double_pressable[button.index()])
+            }
+            InputEvent::DoublePress(button) => {
+                let state = self.state(button);
+                state.pressed && state.double_press
+            }
+            InputEvent::LongPress(button) => self.state(button).long_pressed,
+            InputEvent::Combo(hold, button) => {
+                hold != button
+                    && self.state(hold).is_held()
+                    && self.state(button).is_pressed_or_repeated()
+            }
+            InputEvent::MouseClick => mouse().pressed & MOUSE_LEFT != 0,
+            InputEvent::MouseRightClick => mouse().pressed & MOUSE_RIGHT != 0,
+            InputEvent::MouseDrag => mouse().drag_steps != 0,
+            InputEvent::MouseWheel => mouse().wheel_steps != 0,
+        }
     }
 
     pub fn tick(&mut self) {
-        if !self.initialized {
-            self.init()
+        let gamepad = unsafe { *self.gamepad };
+        for button in Button::ALL {
+            self.buttons[button.index()]
+                .update(gamepad & button.mask() != 0, !button.is_modifier());
         }
-        let gamepad = unsafe { *GAMEPAD1 };
-        for StoredHandler { ref handler, event } in &self.handlers {
-            match event {
-                InputEvent::Button1Press
-                    if self.is_button1_pressed() && !self.double_press_activated =>
-                {
-                    handler(&self)
-                }
-                InputEvent::Button1DoublePress if self.is_button1_pressed() => {
-                    if let Some(last) = self.get_last_fire(InputEvent::Button1Press) {
-                        let now = Winstant::now();
-                        let max = *last + Duration::from_millis(200);
-                        let min = *last + Duration::from_frames(2);
-                        if now < max && now > min {
-                            handler(&self);
-                            self.double_press_activated = true;
-                        }
+        for button in Button::ALL {
+            if self.state(button).is_pressed_or_repeated() {
+                for hold in Button::ALL {
+                    if hold != button && hold.is_modifier() && self.state(hold).is_held() {
+                        self.buttons[hold.index()].combined = true;
                     }
                 }
-                InputEvent::Button2Press if self.is_button2_pressed() => handler(&self),
-                InputEvent::ButtonDownPress if gamepad & BUTTON_DOWN != 0 => handler(&self),
-                InputEvent::ButtonUpPress if gamepad & BUTTON_UP != 0 => handler(&self),
-                InputEvent::ButtonLeftPress if gamepad & BUTTON_LEFT != 0 => handler(&self),
-                InputEvent::ButtonRightPress if gamepad & BUTTON_RIGHT != 0 => handler(&self),
-                _ => {}
             }
         }
-        let now = Winstant::now();
-        match self.get_last_fire(InputEvent::Button1Press) {
-            None => {
-                if self.is_button1_pressed() {
-                    let map = self.last_fire.as_mut().unwrap();
-                    map.insert(InputEvent::Button1Press, now);
-                }
+
+        // Handlers that change screens replace the listeners, the events of this
+        // frame must not reach the new screen
+        let handlers = std::mem::take(&mut self.handlers);
+        let mut double_pressable = [false; 6];
+        for StoredHandler { event, .. } in &handlers {
+            if let InputEvent::DoublePress(button) = event {
+                double_pressable[button.index()] = true;
             }
-            Some(last) => {
-                if self.double_press_activated && *last + Duration::from_millis(200) <= now {
-                    self.double_press_activated = false;
-                }
-                if self.is_button1_pressed() {
-                    let map = self.last_fire.as_mut().unwrap();
-                    map.insert(InputEvent::Button1Press, now);
-                }
+        }
+        for StoredHandler { handler, event } in &handlers {
+            if self.fires(event, &double_pressable) {
+                self.repeating = match event {
+                    InputEvent::Press(button) | InputEvent::Combo(_, button) => {
+                        !self.state(*button).pressed
+                    }
+                    _ => false,
+                };
+                handler(self);
+            }
+            if !self.handlers.is_empty() {
+                break;
             }
         }
+        if self.handlers.is_empty() {
+            self.handlers = handlers;
+        }
+    }
 
-        // if *last + Duration::from_frames(2) >= now {
-
-        // }
+    // Gamepads are consecutive bytes starting with the first player's
+    pub fn player(&self) -> usize {
+        self.gamepad as usize - GAMEPAD1 as usize
     }
 
-    pub fn is_button2_pressed(&self) -> bool {
-        let gamepad = unsafe { *GAMEPAD1 };
-        gamepad & BUTTON_2 != 0
+    pub fn is_any_held(&self) -> bool {
+        self.buttons.iter().any(|a| a.is_held())
     }
 
-    pub fn is_button1_pressed(&self) -> bool {
-        let gamepad = unsafe { *GAMEPAD1 };
-        gamepad & BUTTON_1 != 0
+    // Whether the press being handled comes from holding the button down
+    pub fn is_repeat(&self) -> bool {
+        self.repeating
     }
 }
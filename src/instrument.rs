@@ -1,17 +1,50 @@
-use std::time::Duration;
-
 use crate::{
-    inputs::{InputEvent, Inputs},
-    navigation::go_to_pattern_screen,
+    controls::Action,
+    drums::{drum, Drum, KitParams, DEFAULT_DRUM_KIT, DRUM_COUNT, DRUM_KIT_SIZE},
+    inputs::{
+        mouse,
+        Button::{Button1, Down, Left, Right, Up},
+        InputEvent, Inputs,
+    },
+    macros::{MacroKind, Sequence, MACRO_KINDS},
+    navigation::{add_header_handlers, go_to_macro_screen, go_to_pattern_screen},
+    notes::{Note, NOTE_FREQ},
+    render::instrument_input_at,
     screen::Screen,
-    timers::{ActionId, TIMERS},
-    tracker::{PlayMode, TRACKER},
-    wasm4::{TONE_MODE1, TONE_MODE2, TONE_MODE3, TONE_MODE4},
-    notes::{NOTE_FREQ, Note}
+    tracker::{tracker, PlayMode},
+    tuning::{Tuning, MAX_CENTS},
+    wasm4::{TONE_MODE1, TONE_MODE2, TONE_MODE3, TONE_MODE4, TONE_PAN_LEFT, TONE_PAN_RIGHT},
 };
 
 pub const MAX_INSTRUMENTS: usize = 0x20;
 
+pub const INSTRUMENT_NAME_LENGTH: usize = 6;
+
+// Index in this table is what gets saved, so only append to it
+const NAME_CHARSET: &[u8] = b" ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-#+.";
+
+fn name_char_to_code(c: u8) -> u8 {
+    NAME_CHARSET.iter().position(|&a| a == c).unwrap_or(0) as u8
+}
+
+fn name_code_to_char(code: u8) -> u8 {
+    *NAME_CHARSET.get(code as usize).unwrap_or(&b' ')
+}
+
+pub fn next_name_char(c: u8) -> u8 {
+    name_code_to_char((name_char_to_code(c) + 1) % NAME_CHARSET.len() as u8)
+}
+
+pub fn prev_name_char(c: u8) -> u8 {
+    let code = name_char_to_code(c);
+    let prev_code = if code == 0 {
+        NAME_CHARSET.len() as u8 - 1
+    } else {
+        code - 1
+    };
+    name_code_to_char(prev_code)
+}
+
 #[derive(Clone, Copy, Default)]
 pub enum DutyCycle {
     #[default]
@@ -22,7 +55,7 @@ pub enum DutyCycle {
 }
 
 impl DutyCycle {
-    pub fn to_flag(&self) -> u32 {
+    pub fn to_flag(self) -> u32 {
         match self {
             Self::Eighth => TONE_MODE1,
             Self::Fourth => TONE_MODE2,
@@ -31,6 +64,15 @@ impl DutyCycle {
         }
     }
 
+    pub fn from_bits(bits: u8) -> Self {
+        match bits {
+            1 => DutyCycle::Fourth,
+            2 => DutyCycle::Half,
+            3 => DutyCycle::ThreeFourth,
+            _ => DutyCycle::Eighth,
+        }
+    }
+
     pub fn next(&self) -> Self {
         match self {
             DutyCycle::Eighth => DutyCycle::Fourth,
@@ -50,6 +92,128 @@ impl DutyCycle {
     }
 }
 
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Pan {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+impl Pan {
+    pub fn to_flag(self) -> u32 {
+        match self {
+            Self::Left => TONE_PAN_LEFT,
+            Self::Center => 0,
+            Self::Right => TONE_PAN_RIGHT,
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            Pan::Left => Pan::Center,
+            Pan::Center => Pan::Right,
+            Pan::Right => Pan::Right,
+        }
+    }
+
+    pub fn prev(&self) -> Self {
+        match self {
+            Pan::Left => Pan::Left,
+            Pan::Center => Pan::Left,
+            Pan::Right => Pan::Center,
+        }
+    }
+
+    pub fn to_render(self) -> &'static str {
+        match self {
+            Pan::Left => "L",
+            Pan::Center => "C",
+            Pan::Right => "R",
+        }
+    }
+
+    pub fn to_bits(self) -> u8 {
+        match self {
+            Pan::Center => 0,
+            Pan::Left => 1,
+            Pan::Right => 2,
+        }
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        match bits {
+            1 => Pan::Left,
+            2 => Pan::Right,
+            _ => Pan::Center,
+        }
+    }
+}
+
+const MAX_LFO_DEPTH: u8 = 0x0f;
+const MAX_LFO_SPEED: u8 = 0x0f;
+
+// Vibrato depth is counted in eighths of a semitone, in cents times two
+const VIBRATO_DEPTH_STEP: i32 = 25;
+
+pub const LFO_PEAK: i32 = 127;
+
+// A quarter of a 64 step sine cycle, up to LFO_PEAK
+const QUARTER_SINE: [u8; 17] = [
+    0, 12, 25, 37, 49, 60, 71, 81, 90, 98, 106, 112, 117, 122, 125, 126, 127,
+];
+
+fn sine(step: u32) -> i32 {
+    let half = (step % 32) as usize;
+    let value = QUARTER_SINE[half.min(32 - half)] as i32;
+    if step % 64 < 32 {
+        value
+    } else {
+        -value
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Lfo {
+    depth: u8,
+    speed: u8,
+    delay: u8,
+}
+
+impl Lfo {
+    pub const fn off() -> Self {
+        Lfo {
+            depth: 0,
+            speed: 0,
+            delay: 0,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.depth > 0 && self.speed > 0
+    }
+
+    // Sine wave starting after `delay` frames, speed 1 is one cycle per 64 frames
+    pub fn wave_at(&self, frame: u32) -> i32 {
+        if frame < self.delay as u32 {
+            return 0;
+        }
+        sine((frame - self.delay as u32) * self.speed as u32)
+    }
+
+    pub fn to_bytes(self) -> [u8; 2] {
+        [self.depth << 4 | self.speed, self.delay]
+    }
+
+    pub fn from_bytes(bytes: [u8; 2]) -> Self {
+        Lfo {
+            depth: bytes[0] >> 4,
+            speed: bytes[0] & 0x0f,
+            delay: bytes[1],
+        }
+    }
+}
+
 #[derive(Clone, Copy, Default)]
 pub struct Instrument {
     duty_cycle: DutyCycle,
@@ -60,12 +224,25 @@ pub struct Instrument {
     volume: u8,
     peak: u8,
     note_sweep: i8,
+    glide: u8,
+    finetune: i8,
+    pan: Pan,
+    drum_kit: bool,
+    // Drum of every kit key, indexes into DRUMS
+    drums: [u8; DRUM_KIT_SIZE],
+    kit: KitParams,
+    name: [u8; INSTRUMENT_NAME_LENGTH],
+    macros: [Sequence; MACRO_KINDS],
+    vibrato: Lfo,
+    tremolo: Lfo,
 }
 
 const MAX_VOLUME: u8 = 0x64;
+const MAX_GLIDE: u8 = 0x07;
 const MAX_PEAK: u8 = 0x64;
 
 impl Instrument {
+    #[allow(clippy::too_many_arguments)]
     pub const fn new(
         duty_cycle: DutyCycle,
         attack: u8,
@@ -85,9 +262,29 @@ impl Instrument {
             volume,
             peak,
             note_sweep,
+            glide: 0,
+            finetune: 0,
+            pan: Pan::Center,
+            drum_kit: false,
+            drums: DEFAULT_DRUM_KIT,
+            kit: KitParams::new(),
+            name: [b' '; INSTRUMENT_NAME_LENGTH],
+            macros: [Sequence::empty(); MACRO_KINDS],
+            vibrato: Lfo::off(),
+            tremolo: Lfo::off(),
         }
     }
 
+    pub const fn named(mut self, name: &[u8; INSTRUMENT_NAME_LENGTH]) -> Instrument {
+        self.name = *name;
+        self
+    }
+
+    pub const fn as_drum_kit(mut self) -> Instrument {
+        self.drum_kit = true;
+        self
+    }
+
     pub fn duty_cycle(&self) -> DutyCycle {
         self.duty_cycle
     }
@@ -120,6 +317,104 @@ impl Instrument {
         self.note_sweep
     }
 
+    // Ticks to slide from the previous note, replaces the note sweep when set
+    pub fn glide(&self) -> u8 {
+        self.glide
+    }
+
+    // Cents added to every pitch of the instrument
+    pub fn finetune(&self) -> i8 {
+        self.finetune
+    }
+
+    pub fn pan(&self) -> Pan {
+        self.pan
+    }
+
+    // On the noise channel a drum kit plays a drum from DRUMS instead of a pitch
+    pub fn is_drum_kit(&self) -> bool {
+        self.drum_kit
+    }
+
+    pub fn drums(&self) -> &[u8; DRUM_KIT_SIZE] {
+        &self.drums
+    }
+
+    pub fn kit_params(&self) -> &KitParams {
+        &self.kit
+    }
+
+    pub fn kit_drum(&self, key: usize) -> Drum {
+        self.kit.apply(drum(self.drums[key] as usize))
+    }
+
+    pub fn macro_sequence(&self, kind: MacroKind) -> &Sequence {
+        &self.macros[kind.index()]
+    }
+
+    pub fn has_macros(&self) -> bool {
+        self.macros.iter().any(|sequence| !sequence.is_empty())
+    }
+
+    pub fn vibrato(&self) -> &Lfo {
+        &self.vibrato
+    }
+
+    pub fn tremolo(&self) -> &Lfo {
+        &self.tremolo
+    }
+
+    pub fn vibrato_depth(&self) -> u8 {
+        self.vibrato.depth
+    }
+
+    pub fn vibrato_speed(&self) -> u8 {
+        self.vibrato.speed
+    }
+
+    pub fn vibrato_delay(&self) -> u8 {
+        self.vibrato.delay
+    }
+
+    pub fn tremolo_depth(&self) -> u8 {
+        self.tremolo.depth
+    }
+
+    pub fn tremolo_speed(&self) -> u8 {
+        self.tremolo.speed
+    }
+
+    pub fn tremolo_delay(&self) -> u8 {
+        self.tremolo.delay
+    }
+
+    pub fn has_lfo(&self) -> bool {
+        self.vibrato.is_active() || self.tremolo.is_active()
+    }
+
+    // Pitch offset in cents `frame` frames into the note
+    pub fn vibrato_at(&self, frame: u32) -> i32 {
+        self.vibrato.wave_at(frame) * self.vibrato.depth as i32 * VIBRATO_DEPTH_STEP
+            / (2 * LFO_PEAK)
+    }
+
+    // Volume in percent `frame` frames into the note
+    pub fn tremolo_at(&self, frame: u32) -> u32 {
+        let wave = self.tremolo.wave_at(frame);
+        let dip = wave * wave * self.tremolo.depth as i32 * 100
+            / (LFO_PEAK * LFO_PEAK * MAX_LFO_DEPTH as i32);
+        100 - dip as u32
+    }
+
+    // Names only hold NAME_CHARSET characters or preset ASCII literals
+    pub fn name(&self) -> &str {
+        unsafe { std::str::from_utf8_unchecked(&self.name) }
+    }
+
+    pub fn has_name(&self) -> bool {
+        self.name.iter().any(|&c| c != b' ')
+    }
+
     pub fn update_duty_cycle<F>(&mut self, f: F)
     where
         F: FnOnce(DutyCycle) -> DutyCycle,
@@ -177,45 +472,193 @@ impl Instrument {
         self.note_sweep = f(self.note_sweep).clamp(-max_sweep, max_sweep)
     }
 
-    pub fn to_bytes(&self, api_version: u8) -> (u8, u8, u8, u8, u8, u8, u8, i8) {
+    pub fn update_glide<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.glide = f(self.glide).clamp(0, MAX_GLIDE)
+    }
+
+    pub fn update_finetune<F>(&mut self, f: F)
+    where
+        F: FnOnce(i8) -> i8,
+    {
+        self.finetune = f(self.finetune).clamp(-MAX_CENTS, MAX_CENTS)
+    }
+
+    pub fn update_pan<F>(&mut self, f: F)
+    where
+        F: FnOnce(Pan) -> Pan,
+    {
+        self.pan = f(self.pan)
+    }
+
+    pub fn update_vibrato_depth<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.vibrato.depth = f(self.vibrato.depth).clamp(0, MAX_LFO_DEPTH)
+    }
+
+    pub fn update_vibrato_speed<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.vibrato.speed = f(self.vibrato.speed).clamp(0, MAX_LFO_SPEED)
+    }
+
+    pub fn update_vibrato_delay<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.vibrato.delay = f(self.vibrato.delay)
+    }
+
+    pub fn update_tremolo_depth<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.tremolo.depth = f(self.tremolo.depth).clamp(0, MAX_LFO_DEPTH)
+    }
+
+    pub fn update_tremolo_speed<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.tremolo.speed = f(self.tremolo.speed).clamp(0, MAX_LFO_SPEED)
+    }
+
+    pub fn update_tremolo_delay<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.tremolo.delay = f(self.tremolo.delay)
+    }
+
+    pub fn set_lfos(&mut self, vibrato: Lfo, tremolo: Lfo) {
+        self.vibrato = vibrato;
+        self.tremolo = tremolo;
+    }
+
+    pub fn toggle_drum_kit(&mut self) {
+        self.drum_kit = !self.drum_kit
+    }
+
+    pub fn update_kit_drum<F>(&mut self, key: usize, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        if let Some(drum) = self.drums.get_mut(key) {
+            *drum = f(*drum).min(DRUM_COUNT as u8 - 1)
+        }
+    }
+
+    pub fn set_drums(&mut self, drums: &[u8]) {
+        for (drum, &index) in self.drums.iter_mut().zip(drums) {
+            *drum = index.min(DRUM_COUNT as u8 - 1)
+        }
+    }
+
+    pub fn update_kit_params<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut KitParams),
+    {
+        f(&mut self.kit)
+    }
+
+    pub fn update_macro<F>(&mut self, kind: MacroKind, f: F)
+    where
+        F: FnOnce(&mut Sequence),
+    {
+        f(&mut self.macros[kind.index()])
+    }
+
+    pub fn update_name_char<F>(&mut self, position: usize, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        if let Some(c) = self.name.get_mut(position) {
+            *c = f(*c)
+        }
+    }
+
+    pub fn name_codes(&self) -> [u8; INSTRUMENT_NAME_LENGTH] {
+        self.name.map(name_char_to_code)
+    }
+
+    pub fn set_name_codes(&mut self, codes: &[u8]) {
+        for (c, &code) in self.name.iter_mut().zip(codes) {
+            *c = name_code_to_char(code)
+        }
+    }
+
+    pub fn to_bytes(self, api_version: u8) -> (u8, u8, u8, u8, u8, u8, u8, i8) {
         match api_version {
-            1 => {
-            (
+            1 | 2 => (
                 match self.duty_cycle {
                     DutyCycle::Eighth => 0,
                     DutyCycle::Fourth => 1,
                     DutyCycle::Half => 2,
                     DutyCycle::ThreeFourth => 3,
-                },
+                } | self.pan.to_bits() << 2
+                    | (self.drum_kit as u8) << 4
+                    | self.glide << 5,
                 self.attack,
                 self.decay,
                 self.release,
                 self.sustain,
                 self.volume,
                 self.peak,
-                self.note_sweep
-            )
-            }
+                self.note_sweep,
+            ),
             _ => panic!("Unsupported api version"),
         }
     }
 
     pub fn from_bytes(bytes: (u8, u8, u8, u8, u8, u8, u8, i8)) -> Self {
         Instrument {
-            duty_cycle: match bytes.0 {
-                0 => DutyCycle::Eighth,
-                1 => DutyCycle::Fourth,
-                2 => DutyCycle::Half,
-                3 => DutyCycle::ThreeFourth,
-                _ => DutyCycle::Eighth,
-            },
+            duty_cycle: DutyCycle::from_bits(bytes.0 & 0b11),
             attack: bytes.1,
             decay: bytes.2,
-            sustain: bytes.3,
-            release: bytes.4,
+            // to_bytes has always written the release before the sustain
+            release: bytes.3,
+            sustain: bytes.4,
             volume: bytes.5,
             peak: bytes.6,
             note_sweep: bytes.7,
+            glide: bytes.0 >> 5,
+            finetune: 0,
+            pan: Pan::from_bits(bytes.0 >> 2 & 0b11),
+            drum_kit: bytes.0 >> 4 & 1 == 1,
+            drums: DEFAULT_DRUM_KIT,
+            kit: KitParams::new(),
+            name: [b' '; INSTRUMENT_NAME_LENGTH],
+            macros: [Sequence::empty(); MACRO_KINDS],
+            vibrato: Lfo::off(),
+            tremolo: Lfo::off(),
+        }
+    }
+
+    // Frames before the release phase starts
+    pub fn held_frames(&self) -> u32 {
+        self.attack as u32 + self.decay as u32 + self.sustain as u32
+    }
+
+    // Volume of the `tone` envelope this many frames into the note while it's
+    // held, a zero peak is full volume there too
+    pub fn held_volume_at(&self, frame: u32) -> u32 {
+        let peak = match self.peak {
+            0 => MAX_PEAK as u32,
+            peak => peak as u32,
+        };
+        let (attack, decay) = (self.attack as u32, self.decay as u32);
+        if frame < attack {
+            peak * frame / attack
+        } else if frame < attack + decay {
+            let decayed = (frame - attack) as i32 * (self.volume as i32 - peak as i32);
+            (peak as i32 + decayed / decay as i32) as u32
+        } else {
+            self.volume as u32
         }
     }
 
@@ -230,14 +673,17 @@ impl Instrument {
         (self.peak as u32) << 8 | self.volume as u32
     }
 
-    pub fn get_frequency(&self, initial_note: Note) -> u32 {
-        let sweep_to_index: usize = (initial_note.index as i16 + self.note_sweep as i16).clamp(0, NOTE_FREQ.len() as i16 - 1) as usize;
-        NOTE_FREQ[initial_note.index] as u32 | ((NOTE_FREQ[sweep_to_index] as u32) << 16)
+    pub fn get_frequency(&self, initial_note: Note, tuning: &Tuning) -> u32 {
+        let sweep_to_index: usize = (initial_note.index as i16 + self.note_sweep as i16)
+            .clamp(0, NOTE_FREQ.len() as i16 - 1) as usize;
+        let frequency = |index: usize| tuning.frequency(index, self.finetune);
+        frequency(initial_note.index) | (frequency(sweep_to_index) << 16)
     }
 }
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum InstrumentInput {
+    Name,
     DutyCycle,
     Attack,
     Decay,
@@ -246,198 +692,280 @@ pub enum InstrumentInput {
     Volume,
     Peak,
     NoteSweep,
+    Glide,
+    Finetune,
+    Pan,
+    DrumKit,
+    DrumKey,
+    Drum,
+    KitFrequency,
+    KitSweep,
+    KitEnvelope,
+    KitVolume,
+    VibratoDepth,
+    VibratoSpeed,
+    VibratoDelay,
+    TremoloDepth,
+    TremoloSpeed,
+    TremoloDelay,
+    Preset,
 }
 
-fn on_button_down_press(inputs: &Inputs) {
-    unsafe {
-        if inputs.is_button2_pressed() {
-            TIMERS.run_action_debounced(ActionId::Play, Duration::from_millis(200), || {
-                TRACKER.toggle_play(PlayMode::Pattern)
-            })
-        } else if inputs.is_button1_pressed() {
-            TIMERS.run_action_debounced(
-                ActionId::InstrumentValueDownActionId,
-                Duration::from_millis(200),
-                || {
-                    let selected_instrument = TRACKER.selected_instrument_mut();
-
-                    match TRACKER.instrument_focus() {
-                        InstrumentInput::Attack => {
-                            selected_instrument.update_attack(|a| a.saturating_sub(0x10))
-                        }
-                        InstrumentInput::Decay => {
-                            selected_instrument.update_decay(|a| a.saturating_sub(0x10))
-                        }
-                        InstrumentInput::Sustain => {
-                            selected_instrument.update_sustain(|a| a.saturating_sub(0x10))
-                        }
-                        InstrumentInput::Release => {
-                            selected_instrument.update_release(|a| a.saturating_sub(0x10))
-                        }
-                        InstrumentInput::Volume => {
-                            selected_instrument.update_volume(|a| a.saturating_sub(0x10))
-                        }
-                        InstrumentInput::Peak => {
-                            selected_instrument.update_peak(|a| a.saturating_sub(0x10))
-                        }
-                        InstrumentInput::NoteSweep => {
-                            selected_instrument.update_note_sweep(|a| a.saturating_sub(0x10))
-                        }
-                        InstrumentInput::DutyCycle => {}
-                    }
-                },
-            )
+fn on_button_down_press(_inputs: &Inputs) {
+    unsafe { tracker().instrument_focus_next() }
+}
+
+// Plays a test note after a parameter change, renaming stays quiet
+unsafe fn preview_instrument() {
+    if tracker().instrument_focus() != InstrumentInput::Name {
+        tracker().preview_selected_instrument();
+    }
+}
+
+fn on_button_up_press(_inputs: &Inputs) {
+    unsafe { tracker().instrument_focus_prev() }
+}
+
+// Fine steps in a B1+Up/Down change, 0 for inputs that only take fine steps
+fn coarse_steps(input: InstrumentInput) -> u8 {
+    match input {
+        InstrumentInput::Glide
+        | InstrumentInput::VibratoDepth
+        | InstrumentInput::VibratoSpeed
+        | InstrumentInput::TremoloDepth
+        | InstrumentInput::TremoloSpeed => 1,
+        InstrumentInput::KitFrequency
+        | InstrumentInput::KitSweep
+        | InstrumentInput::KitEnvelope => 4,
+        InstrumentInput::Finetune => 10,
+        InstrumentInput::Attack
+        | InstrumentInput::Decay
+        | InstrumentInput::Sustain
+        | InstrumentInput::Release
+        | InstrumentInput::Volume
+        | InstrumentInput::Peak
+        | InstrumentInput::NoteSweep
+        | InstrumentInput::KitVolume
+        | InstrumentInput::VibratoDelay
+        | InstrumentInput::TremoloDelay => 0x10,
+        InstrumentInput::Name
+        | InstrumentInput::DutyCycle
+        | InstrumentInput::Pan
+        | InstrumentInput::DrumKit
+        | InstrumentInput::DrumKey
+        | InstrumentInput::Drum
+        | InstrumentInput::Preset => 0,
+    }
+}
+
+// Coarse change of the focused value, the name and presets have their own
+unsafe fn coarse_step_focused_value(increase: bool) {
+    let instrument = *tracker().selected_instrument();
+    match tracker().instrument_focus() {
+        InstrumentInput::Name => {
+            let name_char = if increase {
+                next_name_char
+            } else {
+                prev_name_char
+            };
+            let cursor = tracker().instrument_name_cursor();
+            tracker()
+                .selected_instrument_mut()
+                .update_name_char(cursor, name_char)
+        }
+        InstrumentInput::Preset => {
+            if increase {
+                tracker().load_selected_preset()
+            }
+        }
+        input => {
+            for _ in 0..coarse_steps(input) {
+                step_focused_value(increase)
+            }
+        }
+    }
+    tracker().fit_selected_instrument(instrument);
+    preview_instrument();
+}
+
+fn on_button_1_down_press(_inputs: &Inputs) {
+    unsafe { coarse_step_focused_value(false) }
+}
+
+fn on_button_1_up_press(_inputs: &Inputs) {
+    unsafe { coarse_step_focused_value(true) }
+}
+
+// Fine change of the focused value, from B1+Left/Right and mouse drags
+unsafe fn step_focused_value(increase: bool) {
+    let step = |a: u8| {
+        if increase {
+            a.saturating_add(1)
+        } else {
+            a.saturating_sub(1)
+        }
+    };
+    let signed_step = |a: i8| {
+        if increase {
+            a.saturating_add(1)
         } else {
-            TIMERS.run_action_debounced(
-                ActionId::InstrumentNextInputActionId,
-                Duration::from_millis(200),
-                || TRACKER.instrument_focus_next(),
-            )
+            a.saturating_sub(1)
+        }
+    };
+    let selected_instrument = tracker().selected_instrument_mut();
+
+    match tracker().instrument_focus() {
+        InstrumentInput::Name => {
+            if increase {
+                tracker().next_instrument_name_cursor()
+            } else {
+                tracker().prev_instrument_name_cursor()
+            }
+        }
+        InstrumentInput::DutyCycle => {
+            selected_instrument.update_duty_cycle(|a| if increase { a.next() } else { a.prev() })
+        }
+        InstrumentInput::Attack => selected_instrument.update_attack(step),
+        InstrumentInput::Decay => selected_instrument.update_decay(step),
+        InstrumentInput::Sustain => selected_instrument.update_sustain(step),
+        InstrumentInput::Release => selected_instrument.update_release(step),
+        InstrumentInput::Volume => selected_instrument.update_volume(step),
+        InstrumentInput::Peak => selected_instrument.update_peak(step),
+        InstrumentInput::NoteSweep => selected_instrument.update_note_sweep(signed_step),
+        InstrumentInput::Glide => selected_instrument.update_glide(step),
+        InstrumentInput::Finetune => selected_instrument.update_finetune(signed_step),
+        InstrumentInput::Pan => {
+            selected_instrument.update_pan(|a| if increase { a.next() } else { a.prev() })
+        }
+        InstrumentInput::DrumKit => selected_instrument.toggle_drum_kit(),
+        InstrumentInput::DrumKey => tracker().update_drum_key(step),
+        InstrumentInput::Drum => selected_instrument.update_kit_drum(tracker().drum_key(), step),
+        InstrumentInput::KitFrequency => {
+            selected_instrument.update_kit_params(|kit| kit.update_frequency(signed_step))
+        }
+        InstrumentInput::KitSweep => {
+            selected_instrument.update_kit_params(|kit| kit.update_sweep(signed_step))
+        }
+        InstrumentInput::KitEnvelope => {
+            selected_instrument.update_kit_params(|kit| kit.update_envelope(signed_step))
+        }
+        InstrumentInput::KitVolume => {
+            selected_instrument.update_kit_params(|kit| kit.update_volume(step))
+        }
+        InstrumentInput::VibratoDepth => selected_instrument.update_vibrato_depth(step),
+        InstrumentInput::VibratoSpeed => selected_instrument.update_vibrato_speed(step),
+        InstrumentInput::VibratoDelay => selected_instrument.update_vibrato_delay(step),
+        InstrumentInput::TremoloDepth => selected_instrument.update_tremolo_depth(step),
+        InstrumentInput::TremoloSpeed => selected_instrument.update_tremolo_speed(step),
+        InstrumentInput::TremoloDelay => selected_instrument.update_tremolo_delay(step),
+        InstrumentInput::Preset => {
+            if increase {
+                tracker().next_preset()
+            } else {
+                tracker().prev_preset()
+            }
         }
     }
 }
 
-fn on_button_up_press(inputs: &Inputs) {
+fn on_button_1_left_press(_inputs: &Inputs) {
     unsafe {
-        if inputs.is_button1_pressed() {
-            TIMERS.run_action_debounced(
-                ActionId::InstrumentValueUpActionId,
-                Duration::from_millis(200),
-                || {
-                    let selected_instrument = TRACKER.selected_instrument_mut();
-
-                    match TRACKER.instrument_focus() {
-                        InstrumentInput::Attack => {
-                            selected_instrument.update_attack(|a| a.saturating_add(0x10))
-                        }
-                        InstrumentInput::Decay => {
-                            selected_instrument.update_decay(|a| a.saturating_add(0x10))
-                        }
-                        InstrumentInput::Sustain => {
-                            selected_instrument.update_sustain(|a| a.saturating_add(0x10))
-                        }
-                        InstrumentInput::Release => {
-                            selected_instrument.update_release(|a| a.saturating_add(0x10))
-                        }
-                        InstrumentInput::Volume => {
-                            selected_instrument.update_volume(|a| a.saturating_add(0x10))
-                        }
-                        InstrumentInput::Peak => {
-                            selected_instrument.update_peak(|a| a.saturating_add(0x10))
-                        }
-                        InstrumentInput::NoteSweep => {
-                            selected_instrument.update_note_sweep(|a| a.saturating_add(0x10))
-                        }
-                        InstrumentInput::DutyCycle => {}
-                    }
-                },
-            )
-        } else if inputs.is_button2_pressed() {
-            TIMERS.run_action_debounced(ActionId::Persist, Duration::from_millis(1000), || {
-                TRACKER.persist();
-            })
-        } else {
-            TIMERS.run_action_debounced(
-                ActionId::InstrumentPrevInputActionId,
-                Duration::from_millis(200),
-                || TRACKER.instrument_focus_prev(),
-            )
+        let instrument = *tracker().selected_instrument();
+        step_focused_value(false);
+        tracker().fit_selected_instrument(instrument);
+        preview_instrument();
+    }
+}
+
+fn on_button_1_right_press(_inputs: &Inputs) {
+    unsafe {
+        let instrument = *tracker().selected_instrument();
+        step_focused_value(true);
+        tracker().fit_selected_instrument(instrument);
+        preview_instrument();
+    }
+}
+
+fn on_play(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().toggle_play(PlayMode::Pattern)
         }
     }
 }
 
-fn on_button_left_press(inputs: &Inputs) {
+fn on_save(inputs: &Inputs) {
     unsafe {
-        if inputs.is_button2_pressed() {
-            TIMERS.run_action_debounced(
-                ActionId::NavPrevScreen,
-                Duration::from_millis(200),
-                || go_to_pattern_screen(Screen::Instrument),
-            );
-        } else if inputs.is_button1_pressed() {
-            TIMERS.run_action_debounced(
-                ActionId::InstrumentValuePrevActionId,
-                Duration::from_millis(200),
-                || {
-                    let selected_instrument = TRACKER.selected_instrument_mut();
-
-                    match TRACKER.instrument_focus() {
-                        InstrumentInput::DutyCycle => {
-                            selected_instrument.update_duty_cycle(|a| a.prev())
-                        }
-                        InstrumentInput::Attack => {
-                            selected_instrument.update_attack(|a| a.saturating_sub(1))
-                        }
-                        InstrumentInput::Decay => {
-                            selected_instrument.update_decay(|a| a.saturating_sub(1))
-                        }
-                        InstrumentInput::Sustain => {
-                            selected_instrument.update_sustain(|a| a.saturating_sub(1))
-                        }
-                        InstrumentInput::Release => {
-                            selected_instrument.update_release(|a| a.saturating_sub(1))
-                        }
-                        InstrumentInput::Volume => {
-                            selected_instrument.update_volume(|a| a.saturating_sub(1))
-                        }
-                        InstrumentInput::Peak => {
-                            selected_instrument.update_peak(|a| a.saturating_sub(1))
-                        }
-                        InstrumentInput::NoteSweep => {
-                            selected_instrument.update_note_sweep(|a| a.saturating_sub(1))
-                        }
-                    }
-                },
-            )
-        }
-    }
-}
-
-fn on_button_right_press(inputs: &Inputs) {
+        if !inputs.is_repeat() {
+            tracker().persist();
+        }
+    }
+}
+
+fn on_prev_screen(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            go_to_pattern_screen(Screen::Instrument);
+        }
+    }
+}
+
+fn on_next_screen(inputs: &Inputs) {
     unsafe {
-        if inputs.is_button1_pressed() {
-            TIMERS.run_action_debounced(
-                ActionId::InstrumentValueNextActionId,
-                Duration::from_millis(200),
-                || {
-                    let selected_instrument = TRACKER.selected_instrument_mut();
-
-                    match TRACKER.instrument_focus() {
-                        InstrumentInput::DutyCycle => {
-                            selected_instrument.update_duty_cycle(|a| a.next())
-                        }
-                        InstrumentInput::Attack => {
-                            selected_instrument.update_attack(|a| a.saturating_add(1))
-                        }
-                        InstrumentInput::Decay => {
-                            selected_instrument.update_decay(|a| a.saturating_add(1))
-                        }
-                        InstrumentInput::Sustain => {
-                            selected_instrument.update_sustain(|a| a.saturating_add(1))
-                        }
-                        InstrumentInput::Release => {
-                            selected_instrument.update_release(|a| a.saturating_add(1))
-                        }
-                        InstrumentInput::Volume => {
-                            selected_instrument.update_volume(|a| a.saturating_add(1))
-                        }
-                        InstrumentInput::Peak => {
-                            selected_instrument.update_peak(|a| a.saturating_add(1))
-                        }
-                        InstrumentInput::NoteSweep => {
-                            selected_instrument.update_note_sweep(|a| a.saturating_add(1))
-                        }
-                    }
-                },
-            )
+        if !inputs.is_repeat() {
+            go_to_macro_screen(Screen::Instrument);
+        }
+    }
+}
+
+fn on_mouse_click(_inputs: &Inputs) {
+    let (_, y) = mouse().position();
+    unsafe {
+        if let Some(input) = instrument_input_at(tracker().instrument_focus(), y) {
+            tracker().set_instrument_focus(input);
+        }
+    }
+}
+
+// Dragging right raises the focused value one fine step every few pixels
+fn on_mouse_drag(_inputs: &Inputs) {
+    let steps = mouse().drag_steps();
+    unsafe {
+        let instrument = *tracker().selected_instrument();
+        for _ in 0..steps.unsigned_abs() {
+            step_focused_value(steps > 0);
+        }
+        tracker().fit_selected_instrument(instrument);
+        preview_instrument();
+    }
+}
+
+fn on_mouse_wheel(_inputs: &Inputs) {
+    unsafe {
+        for _ in 0..mouse().wheel_steps() {
+            tracker().instrument_focus_next()
+        }
+        for _ in mouse().wheel_steps()..0 {
+            tracker().instrument_focus_prev()
         }
     }
 }
 
 pub fn add_instrument_screen_handlers(inputs: &mut Inputs) {
+    let bindings = unsafe { *tracker().bindings() };
     inputs
-        .listen(InputEvent::ButtonDownPress, on_button_down_press)
-        .listen(InputEvent::ButtonUpPress, on_button_up_press)
-        .listen(InputEvent::ButtonLeftPress, on_button_left_press)
-        .listen(InputEvent::ButtonRightPress, on_button_right_press);
+        .listen(InputEvent::Press(Down), on_button_down_press)
+        .listen(InputEvent::Press(Up), on_button_up_press)
+        .listen(InputEvent::Combo(Button1, Down), on_button_1_down_press)
+        .listen(InputEvent::Combo(Button1, Up), on_button_1_up_press)
+        .listen(InputEvent::Combo(Button1, Left), on_button_1_left_press)
+        .listen(InputEvent::Combo(Button1, Right), on_button_1_right_press)
+        .listen(bindings.event(Action::Play), on_play)
+        .listen(bindings.event(Action::Save), on_save)
+        .listen(bindings.event(Action::PrevScreen), on_prev_screen)
+        .listen(bindings.event(Action::NextScreen), on_next_screen)
+        .listen(InputEvent::MouseClick, on_mouse_click)
+        .listen(InputEvent::MouseDrag, on_mouse_drag)
+        .listen(InputEvent::MouseWheel, on_mouse_wheel);
+    add_header_handlers(inputs);
 }
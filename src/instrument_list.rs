@@ -0,0 +1,123 @@
+use crate::{
+    controls::Action,
+    inputs::{
+        mouse,
+        Button::{Button1, Down, Left, Right, Up},
+        InputEvent, Inputs,
+    },
+    navigation::{add_header_handlers, go_to_controls_screen, go_to_macro_screen},
+    render::instrument_list_index_at,
+    screen::Screen,
+    tracker::{tracker, PlayMode},
+};
+
+const INSTRUMENTS_PER_COLUMN: usize = 0x10;
+
+fn on_button_down_press(_inputs: &Inputs) {
+    unsafe { tracker().next_selected_instrument_index(1) }
+}
+
+fn on_button_up_press(_inputs: &Inputs) {
+    unsafe { tracker().prev_selected_instrument_index(1) }
+}
+
+fn on_button_left_press(_inputs: &Inputs) {
+    unsafe { tracker().prev_selected_instrument_index(INSTRUMENTS_PER_COLUMN) }
+}
+
+fn on_button_right_press(_inputs: &Inputs) {
+    unsafe { tracker().next_selected_instrument_index(INSTRUMENTS_PER_COLUMN) }
+}
+
+fn on_button_1_down_press(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().reset_selected_instrument()
+        }
+    }
+}
+
+fn on_button_1_left_press(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().copy_selected_instrument()
+        }
+    }
+}
+
+fn on_button_1_right_press(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().paste_selected_instrument()
+        }
+    }
+}
+
+fn on_play(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().toggle_play(PlayMode::Pattern)
+        }
+    }
+}
+
+fn on_save(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().persist();
+        }
+    }
+}
+
+fn on_prev_screen(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            go_to_macro_screen(Screen::InstrumentList);
+        }
+    }
+}
+
+fn on_next_screen(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            go_to_controls_screen(Screen::InstrumentList);
+        }
+    }
+}
+
+fn on_mouse_click(_inputs: &Inputs) {
+    let (x, y) = mouse().position();
+    if let Some(index) = instrument_list_index_at(x, y) {
+        unsafe { tracker().set_selected_instrument_index(index) }
+    }
+}
+
+fn on_mouse_wheel(_inputs: &Inputs) {
+    unsafe {
+        for _ in 0..mouse().wheel_steps() {
+            tracker().next_selected_instrument_index(1)
+        }
+        for _ in mouse().wheel_steps()..0 {
+            tracker().prev_selected_instrument_index(1)
+        }
+    }
+}
+
+pub fn add_instrument_list_screen_handlers(inputs: &mut Inputs) {
+    let bindings = unsafe { *tracker().bindings() };
+    inputs
+        .listen(InputEvent::Press(Down), on_button_down_press)
+        .listen(InputEvent::Press(Up), on_button_up_press)
+        .listen(InputEvent::Press(Left), on_button_left_press)
+        .listen(InputEvent::Press(Right), on_button_right_press)
+        .listen(InputEvent::Combo(Button1, Down), on_button_1_down_press)
+        .listen(InputEvent::Combo(Button1, Left), on_button_1_left_press)
+        .listen(InputEvent::Combo(Button1, Right), on_button_1_right_press)
+        .listen(bindings.event(Action::Play), on_play)
+        .listen(bindings.event(Action::Save), on_save)
+        .listen(bindings.event(Action::PrevScreen), on_prev_screen)
+        .listen(bindings.event(Action::NextScreen), on_next_screen)
+        .listen(InputEvent::MouseClick, on_mouse_click)
+        .listen(InputEvent::MouseWheel, on_mouse_wheel);
+    add_header_handlers(inputs);
+}
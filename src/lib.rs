@@ -1,52 +1,78 @@
-#[cfg(feature = "buddy-alloc")]
+// The host's allocator serves the unit tests
+#[cfg(all(feature = "buddy-alloc", not(test)))]
 mod alloc;
+mod bits;
 mod channel;
+mod controls;
+mod digits;
+mod drums;
+mod echo;
 mod inputs;
 mod instrument;
+mod instrument_list;
+mod macros;
 mod navigation;
 mod notes;
 mod pattern;
+mod pattern_list;
+mod players;
+mod presets;
 mod render;
+mod scale;
 mod screen;
 mod song;
+mod song_settings;
 mod timers;
 mod tracker;
+mod tuning;
 mod unique_usize;
 mod wasm4;
 mod wtime;
 
-use inputs::Inputs;
+use std::ptr::addr_of_mut;
+
+use inputs::{Inputs, MOUSE};
 use pattern::add_pattern_screen_handlers;
+use players::{reset_player_handlers, tick_players, PLAYERS};
 use render::render_screens;
 // use song::add_song_screen_handlers;
-use timers::TIMERS;
-use tracker::{Tracker, TRACKER};
+use timers::timers;
+use tracker::{tracker, Tracker, TRACKER};
+use wasm4::{GAMEPAD1, GAMEPAD2, GAMEPAD3, GAMEPAD4};
 use wtime::Winstant;
 
-static mut INPUTS: Inputs = Inputs::new();
+// One per player, screens listen on the first one
+static mut INPUTS: [Inputs; PLAYERS] = [
+    Inputs::new(GAMEPAD1),
+    Inputs::new(GAMEPAD2),
+    Inputs::new(GAMEPAD3),
+    Inputs::new(GAMEPAD4),
+];
+
+unsafe fn inputs_mut() -> &'static mut [Inputs; PLAYERS] {
+    &mut *addr_of_mut!(INPUTS)
+}
 
 #[no_mangle]
 unsafe fn start() {
     TRACKER = Tracker::restore();
-    TIMERS.init();
-    add_pattern_screen_handlers(&mut INPUTS);
-    // TRACKER.set_screen(Screen::Song);
-    // add_song_screen_handlers(&mut INPUTS)
+    add_pattern_screen_handlers(&mut inputs_mut()[0]);
+    reset_player_handlers();
+    // tracker().set_screen(Screen::Song);
+    // add_song_screen_handlers(inputs_mut())
 }
 
 #[no_mangle]
 fn update() {
-    let tracker: &Tracker;
-    unsafe {
-        tracker = &TRACKER;
-    };
+    let tracker = unsafe { tracker() };
 
     render_screens(tracker.screens(), tracker);
 
     unsafe {
-        TRACKER.update();
-        INPUTS.tick();
-        TIMERS.tick();
+        tracker.update();
+        (*addr_of_mut!(MOUSE)).tick();
+        tick_players();
+        timers().tick();
     }
 
     Winstant::tick();
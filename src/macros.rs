@@ -0,0 +1,446 @@
+use crate::{
+    controls::Action,
+    inputs::{
+        mouse,
+        Button::{Button1, Down, Left, Right, Up},
+        InputEvent, Inputs,
+    },
+    navigation::{add_header_handlers, go_to_instrument_list_screen, go_to_instrument_screen},
+    render::macro_cell_at,
+    screen::Screen,
+    tracker::{tracker, PlayMode},
+};
+
+pub const MAX_SEQUENCE_LENGTH: usize = 0x10;
+
+pub const MACRO_KINDS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MacroKind {
+    Volume,
+    Arpeggio,
+    Pitch,
+    Duty,
+}
+
+impl MacroKind {
+    pub fn iterator() -> impl Iterator<Item = MacroKind> {
+        [
+            MacroKind::Volume,
+            MacroKind::Arpeggio,
+            MacroKind::Pitch,
+            MacroKind::Duty,
+        ]
+        .iter()
+        .copied()
+    }
+
+    pub fn index(&self) -> usize {
+        match self {
+            MacroKind::Volume => 0,
+            MacroKind::Arpeggio => 1,
+            MacroKind::Pitch => 2,
+            MacroKind::Duty => 3,
+        }
+    }
+
+    pub fn from_index(index: usize) -> Self {
+        match index {
+            1 => MacroKind::Arpeggio,
+            2 => MacroKind::Pitch,
+            3 => MacroKind::Duty,
+            _ => MacroKind::Volume,
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            MacroKind::Volume => MacroKind::Arpeggio,
+            MacroKind::Arpeggio => MacroKind::Pitch,
+            MacroKind::Pitch => MacroKind::Duty,
+            &duty @ MacroKind::Duty => duty,
+        }
+    }
+
+    pub fn prev(&self) -> Self {
+        match self {
+            &volume @ MacroKind::Volume => volume,
+            MacroKind::Arpeggio => MacroKind::Volume,
+            MacroKind::Pitch => MacroKind::Arpeggio,
+            MacroKind::Duty => MacroKind::Pitch,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MacroKind::Volume => "VOL",
+            MacroKind::Arpeggio => "ARP",
+            MacroKind::Pitch => "PIT",
+            MacroKind::Duty => "DTY",
+        }
+    }
+
+    // Volume is a 0..=15 multiplier, arpeggio is in semitones, pitch in Hz
+    pub fn range(&self) -> (i8, i8) {
+        match self {
+            MacroKind::Volume => (0, 15),
+            MacroKind::Arpeggio => (-24, 24),
+            MacroKind::Pitch => (-64, 63),
+            MacroKind::Duty => (0, 3),
+        }
+    }
+
+    pub fn default_value(&self) -> i8 {
+        match self {
+            MacroKind::Volume => 15,
+            MacroKind::Duty => 2,
+            MacroKind::Arpeggio | MacroKind::Pitch => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Sequence {
+    values: [i8; MAX_SEQUENCE_LENGTH],
+    length: u8,
+    loop_point: Option<u8>,
+    release_point: Option<u8>,
+}
+
+impl Sequence {
+    pub const fn empty() -> Self {
+        Sequence {
+            values: [0; MAX_SEQUENCE_LENGTH],
+            length: 0,
+            loop_point: None,
+            release_point: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.length as usize
+    }
+
+    pub fn value(&self, step: usize) -> Option<i8> {
+        if step < self.len() {
+            Some(self.values[step])
+        } else {
+            None
+        }
+    }
+
+    pub fn loop_point(&self) -> Option<u8> {
+        self.loop_point
+    }
+
+    pub fn release_point(&self) -> Option<u8> {
+        self.release_point
+    }
+
+    pub fn update_value<F>(&mut self, kind: MacroKind, step: usize, f: F)
+    where
+        F: FnOnce(i8) -> i8,
+    {
+        if step >= MAX_SEQUENCE_LENGTH {
+            return;
+        }
+        // Editing past the end grows the sequence, repeating its last value
+        while self.len() <= step {
+            let last = self.len().checked_sub(1);
+            self.values[self.len()] = match last {
+                Some(last) => self.values[last],
+                None => kind.default_value(),
+            };
+            self.length += 1;
+        }
+        let (min, max) = kind.range();
+        self.values[step] = f(self.values[step]).clamp(min, max)
+    }
+
+    pub fn truncate(&mut self, length: usize) {
+        self.length = length.min(self.len()) as u8;
+        if self.loop_point.is_some_and(|a| a >= self.length) {
+            self.loop_point = None
+        }
+        if self.release_point.is_some_and(|a| a >= self.length) {
+            self.release_point = None
+        }
+    }
+
+    // Cycles the marker at `step`: none -> loop -> release -> none
+    pub fn cycle_marker(&mut self, step: usize) {
+        if step >= self.len() {
+            return;
+        }
+        let step = Some(step as u8);
+        if self.loop_point == step {
+            self.loop_point = None;
+            self.release_point = step;
+        } else if self.release_point == step {
+            self.release_point = None;
+        } else {
+            self.loop_point = step;
+        }
+    }
+
+    pub fn value_at(&self, position: u8) -> Option<i8> {
+        let last = self.len().checked_sub(1)?;
+        Some(self.values[(position as usize).min(last)])
+    }
+
+    // Before release the sequence loops in loop..=release (or up to its end),
+    // after release it continues past the release point and holds the last value
+    pub fn next_position(&self, position: u8, released: bool) -> u8 {
+        let last = match self.length.checked_sub(1) {
+            Some(last) => last,
+            None => return 0,
+        };
+        if released {
+            match self.release_point {
+                Some(release) if position <= release => (release + 1).min(last),
+                _ => (position + 1).min(last),
+            }
+        } else {
+            let section_end = self.release_point.unwrap_or(last);
+            if position < section_end {
+                position + 1
+            } else {
+                match self.loop_point {
+                    Some(loop_point) if loop_point <= section_end => loop_point,
+                    _ => position,
+                }
+            }
+        }
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = vec![
+            self.length,
+            self.loop_point.unwrap_or(0xff),
+            self.release_point.unwrap_or(0xff),
+        ];
+        bytes.extend(self.values[..self.len()].iter().map(|&a| a as u8));
+        bytes
+    }
+
+    pub fn from_bytes(header: (u8, u8, u8), values: &[u8]) -> Self {
+        let mut sequence = Sequence::empty();
+        sequence.length = header.0.min(MAX_SEQUENCE_LENGTH as u8);
+        for (value, &byte) in sequence.values.iter_mut().zip(values) {
+            *value = byte as i8;
+        }
+        sequence.loop_point = Some(header.1).filter(|&a| a < sequence.length);
+        sequence.release_point = Some(header.2).filter(|&a| a < sequence.length);
+        sequence
+    }
+}
+
+fn on_button_down_press(_inputs: &Inputs) {
+    unsafe { tracker().next_macro_cursor_kind() }
+}
+
+fn on_button_up_press(_inputs: &Inputs) {
+    unsafe { tracker().prev_macro_cursor_kind() }
+}
+
+fn on_button_left_press(_inputs: &Inputs) {
+    unsafe { tracker().prev_macro_cursor_step() }
+}
+
+fn on_button_right_press(_inputs: &Inputs) {
+    unsafe { tracker().next_macro_cursor_step() }
+}
+
+// Shared by the buttons and mouse drags
+unsafe fn update_cursor_value(delta: i8) {
+    let kind = tracker().macro_cursor_kind();
+    let step = tracker().macro_cursor_step();
+    tracker().update_selected_macro(kind, |sequence| {
+        sequence.update_value(kind, step, |a| a.saturating_add(delta))
+    })
+}
+
+fn on_button_1_down_press(_inputs: &Inputs) {
+    unsafe { update_cursor_value(-1) }
+}
+
+fn on_button_1_up_press(_inputs: &Inputs) {
+    unsafe { update_cursor_value(1) }
+}
+
+fn on_button_1_left_press(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            let kind = tracker().macro_cursor_kind();
+            let step = tracker().macro_cursor_step();
+            tracker().update_selected_macro(kind, |sequence| sequence.truncate(step))
+        }
+    }
+}
+
+fn on_button_1_right_press(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            let kind = tracker().macro_cursor_kind();
+            let step = tracker().macro_cursor_step();
+            tracker().update_selected_macro(kind, |sequence| sequence.cycle_marker(step))
+        }
+    }
+}
+
+fn on_play(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().toggle_play(PlayMode::Pattern)
+        }
+    }
+}
+
+fn on_save(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().persist();
+        }
+    }
+}
+
+fn on_prev_screen(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            go_to_instrument_screen(Screen::Macro);
+        }
+    }
+}
+
+fn on_next_screen(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            go_to_instrument_list_screen(Screen::Macro);
+        }
+    }
+}
+
+fn on_mouse_click(_inputs: &Inputs) {
+    let (x, y) = mouse().position();
+    if let Some((kind, step)) = macro_cell_at(x, y) {
+        unsafe { tracker().set_macro_cursor(kind, step) }
+    }
+}
+
+// Dragging right raises the value under the cursor
+fn on_mouse_drag(_inputs: &Inputs) {
+    unsafe { update_cursor_value(mouse().drag_steps() as i8) }
+}
+
+fn on_mouse_wheel(_inputs: &Inputs) {
+    unsafe {
+        for _ in 0..mouse().wheel_steps() {
+            tracker().next_macro_cursor_step()
+        }
+        for _ in mouse().wheel_steps()..0 {
+            tracker().prev_macro_cursor_step()
+        }
+    }
+}
+
+pub fn add_macro_screen_handlers(inputs: &mut Inputs) {
+    let bindings = unsafe { *tracker().bindings() };
+    inputs
+        .listen(InputEvent::Press(Down), on_button_down_press)
+        .listen(InputEvent::Press(Up), on_button_up_press)
+        .listen(InputEvent::Press(Left), on_button_left_press)
+        .listen(InputEvent::Press(Right), on_button_right_press)
+        .listen(InputEvent::Combo(Button1, Down), on_button_1_down_press)
+        .listen(InputEvent::Combo(Button1, Up), on_button_1_up_press)
+        .listen(InputEvent::Combo(Button1, Left), on_button_1_left_press)
+        .listen(InputEvent::Combo(Button1, Right), on_button_1_right_press)
+        .listen(bindings.event(Action::Play), on_play)
+        .listen(bindings.event(Action::Save), on_save)
+        .listen(bindings.event(Action::PrevScreen), on_prev_screen)
+        .listen(bindings.event(Action::NextScreen), on_next_screen)
+        .listen(InputEvent::MouseClick, on_mouse_click)
+        .listen(InputEvent::MouseDrag, on_mouse_drag)
+        .listen(InputEvent::MouseWheel, on_mouse_wheel);
+    add_header_handlers(inputs);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(loop_point: Option<u8>, release_point: Option<u8>) -> Sequence {
+        Sequence {
+            values: [1, 2, 3, 4, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            length: 5,
+            loop_point,
+            release_point,
+        }
+    }
+
+    fn positions(sequence: &Sequence, start: u8, released: bool, count: usize) -> Vec<u8> {
+        let mut position = start;
+        let mut positions = vec![];
+        for _ in 0..count {
+            position = sequence.next_position(position, released);
+            positions.push(position);
+        }
+        positions
+    }
+
+    #[test]
+    fn holds_the_last_step_without_markers() {
+        assert_eq!(
+            positions(&sequence(None, None), 0, false, 6),
+            [1, 2, 3, 4, 4, 4]
+        );
+        assert_eq!(sequence(None, None).value_at(9), Some(5));
+    }
+
+    #[test]
+    fn loops_to_the_end_without_a_release_point() {
+        assert_eq!(
+            positions(&sequence(Some(2), None), 0, false, 6),
+            [1, 2, 3, 4, 2, 3]
+        );
+    }
+
+    #[test]
+    fn loops_up_to_the_release_point_while_held() {
+        let sequence = sequence(Some(1), Some(3));
+        assert_eq!(positions(&sequence, 0, false, 6), [1, 2, 3, 1, 2, 3]);
+    }
+
+    #[test]
+    fn holds_the_release_point_without_a_loop() {
+        assert_eq!(
+            positions(&sequence(None, Some(2)), 0, false, 4),
+            [1, 2, 2, 2]
+        );
+    }
+
+    #[test]
+    fn continues_past_the_release_point_once_released() {
+        let sequence = sequence(Some(1), Some(2));
+        assert_eq!(positions(&sequence, 1, true, 4), [3, 4, 4, 4]);
+        assert_eq!(positions(&sequence, 3, true, 2), [4, 4]);
+    }
+
+    #[test]
+    fn stays_at_the_start_when_empty() {
+        assert_eq!(Sequence::empty().next_position(3, false), 0);
+        assert_eq!(Sequence::empty().value_at(0), None);
+    }
+
+    #[test]
+    fn drops_markers_past_a_truncated_end() {
+        let mut sequence = sequence(Some(1), Some(3));
+        sequence.truncate(3);
+        assert_eq!(sequence.len(), 3);
+        assert_eq!(sequence.loop_point(), Some(1));
+        assert_eq!(sequence.release_point(), None);
+    }
+}
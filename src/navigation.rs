@@ -1,66 +1,122 @@
-use std::{time::Duration};
+use std::time::Duration;
 
 use crate::{
+    controls::add_controls_screen_handlers,
+    inputs::{mouse, InputEvent, Inputs},
+    inputs_mut,
     instrument::add_instrument_screen_handlers,
+    instrument_list::add_instrument_list_screen_handlers,
+    macros::add_macro_screen_handlers,
     pattern::add_pattern_screen_handlers,
+    pattern_list::add_pattern_list_screen_handlers,
+    render::is_header,
     screen::{Screen, Screens},
     song::add_song_screen_handlers,
-    timers::TIMERS,
-    tracker::TRACKER,
+    song_settings::add_song_settings_screen_handlers,
+    timers::timers,
+    tracker::tracker,
     unique_usize::get_unique_usize,
-    wtime::Winstant,
-    INPUTS,
+    wtime::{Frames, Winstant},
 };
 
 const ANIM_DURATION_MS: u64 = 300;
 
 unsafe fn run_transition(from: Screen, to: Screen, duration: Duration) {
     let start = Winstant::now();
-    let duration_ms_f32 = duration.as_millis() as f32;
+    let frames = duration.as_frames();
 
     let interval_id = get_unique_usize();
-    TIMERS.set_interval(
+    timers().set_interval(
         interval_id,
         move || {
-            let now = Winstant::now();
-            let since_start = now.duration_since(start);
-            let progress: f32 = since_start.as_millis() as f32 / duration_ms_f32;
-            if progress < 1.0 {
-                TRACKER.set_screens(Screens::Transition(from, to, progress));
-            } else {
-                TRACKER.set_screens(Screens::Single(to));
-                TIMERS.cancel_interval(interval_id);
-            }
+            let since_start = Winstant::now().frames_since(start);
+            let progress: f32 = since_start as f32 / frames as f32;
+            tracker().set_screens(Screens::Transition(from, to, progress.min(1.0)));
         },
         1,
     );
+    timers().set_timeout(
+        get_unique_usize(),
+        move || {
+            tracker().set_screens(Screens::Single(to));
+            timers().cancel(interval_id);
+        },
+        frames as usize,
+    );
 }
 
 pub unsafe fn go_to_pattern_screen(from: Screen) {
-    TRACKER.set_screens(Screens::Single(Screen::Pattern));
-    run_transition(
-        from,
-        Screen::Pattern,
-        Duration::from_millis(ANIM_DURATION_MS),
-    );
-    INPUTS.unlisten();
-    add_pattern_screen_handlers(&mut INPUTS);
+    go_to_screen(from, Screen::Pattern)
 }
 
 pub unsafe fn go_to_instrument_screen(from: Screen) {
-    TRACKER.set_screens(Screens::Transition(from, Screen::Instrument, 0.0));
-    run_transition(
-        from,
-        Screen::Instrument,
-        Duration::from_millis(ANIM_DURATION_MS),
-    );
-    INPUTS.unlisten();
-    add_instrument_screen_handlers(&mut INPUTS);
+    go_to_screen(from, Screen::Instrument)
+}
+
+pub unsafe fn go_to_macro_screen(from: Screen) {
+    go_to_screen(from, Screen::Macro)
+}
+
+pub unsafe fn go_to_instrument_list_screen(from: Screen) {
+    go_to_screen(from, Screen::InstrumentList)
 }
 
 pub unsafe fn go_to_song_screen(from: Screen) {
-    TRACKER.set_screens(Screens::Single(Screen::Song));
-    run_transition(from, Screen::Song, Duration::from_millis(ANIM_DURATION_MS));
-    INPUTS.unlisten();
-    add_song_screen_handlers(&mut INPUTS);
+    go_to_screen(from, Screen::Song)
+}
+
+pub unsafe fn go_to_pattern_list_screen(from: Screen) {
+    go_to_screen(from, Screen::PatternList)
+}
+
+pub unsafe fn go_to_song_settings_screen(from: Screen) {
+    go_to_screen(from, Screen::SongSettings)
+}
+
+pub unsafe fn go_to_controls_screen(from: Screen) {
+    go_to_screen(from, Screen::Controls)
+}
+
+pub unsafe fn go_to_screen(from: Screen, to: Screen) {
+    tracker().set_screens(Screens::Transition(from, to, 0.0));
+    run_transition(from, to, Duration::from_millis(ANIM_DURATION_MS));
+    let inputs = &mut inputs_mut()[0];
+    inputs.unlisten();
+    match to {
+        Screen::Song => add_song_screen_handlers(inputs),
+        Screen::PatternList => add_pattern_list_screen_handlers(inputs),
+        Screen::Pattern => add_pattern_screen_handlers(inputs),
+        Screen::Instrument => add_instrument_screen_handlers(inputs),
+        Screen::Macro => add_macro_screen_handlers(inputs),
+        Screen::InstrumentList => add_instrument_list_screen_handlers(inputs),
+        Screen::SongSettings => add_song_settings_screen_handlers(inputs),
+        Screen::Controls => add_controls_screen_handlers(inputs),
+    }
+}
+
+fn on_header_click(_inputs: &Inputs) {
+    unsafe {
+        let screen = tracker().screens().current();
+        let (x, y) = mouse().position();
+        if is_header(screen, x, y) && screen.next() != screen {
+            go_to_screen(screen, screen.next());
+        }
+    }
+}
+
+fn on_header_right_click(_inputs: &Inputs) {
+    unsafe {
+        let screen = tracker().screens().current();
+        let (x, y) = mouse().position();
+        if is_header(screen, x, y) && screen.prev() != screen {
+            go_to_screen(screen, screen.prev());
+        }
+    }
+}
+
+// Clicking a screen header goes to the next screen, right clicking it to the previous one
+pub fn add_header_handlers(inputs: &mut Inputs) {
+    inputs
+        .listen(InputEvent::MouseClick, on_header_click)
+        .listen(InputEvent::MouseRightClick, on_header_right_click);
 }
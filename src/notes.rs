@@ -1,15 +1,8 @@
-use crate::instrument::MAX_INSTRUMENTS;
-
-pub const NOTE_NAME: [&str; 108] = [
-    "C0", "C#0", "D0", "D#0", "E0", "F0", "F#0", "G0", "G#0", "A0", "A#0", "B0", "C1", "C#1", "D1",
-    "D#1", "E1", "F1", "F#1", "G1", "G#1", "A1", "A#1", "B1", "C2", "C#2", "D2", "D#2", "E2", "F2",
-    "F#2", "G2", "G#2", "A2", "A#2", "B2", "C3", "C#3", "D3", "D#3", "E3", "F3", "F#3", "G3",
-    "G#3", "A3", "A#3", "B3", "C4", "C#4", "D4", "D#4", "E4", "F4", "F#4", "G4", "G#4", "A4",
-    "A#4", "B4", "C5", "C#5", "D5", "D#5", "E5", "F5", "F#5", "G5", "G#5", "A5", "A#5", "B5", "C6",
-    "C#6", "D6", "D#6", "E6", "F6", "F#6", "G6", "G#6", "A6", "A#6", "B6", "C7", "C#7", "D7",
-    "D#7", "E7", "F7", "F#7", "G7", "G#7", "A7", "A#7", "B7", "C8", "C#8", "D8", "D#8", "E8", "F8",
-    "F#8", "G8", "G#8", "A8", "A#8", "B8",
-];
+use crate::{
+    digits::decimal,
+    instrument::{Pan, MAX_INSTRUMENTS},
+    scale::Scale,
+};
 
 pub const NOTE_FREQ: [u16; 108] = [
     16, 17, 18, 19, 21, 22, 23, 25, 26, 28, 29, 31, 33, 35, 37, 39, 41, 44, 46, 49, 52, 55, 58, 62,
@@ -22,12 +15,72 @@ pub const NOTE_FREQ: [u16; 108] = [
 
 pub const NOTE_C3_INDEX: usize = 36;
 
+// Step values past the last pitch: a note off starts the release of the
+// playing note, a note cut silences it right away
+pub const NOTE_OFF_INDEX: usize = 0x7e;
+pub const NOTE_CUT_INDEX: usize = 0x7f;
+
 pub const NOTES_PER_OCTAVE: u32 = 12;
 
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Command {
+    #[default]
+    None,
+    Pan(Pan),
+    // Changes the pitch of the playing note without restarting its envelope
+    Legato,
+    // Slides from the previous note, over the instrument glide or one tick
+    Glide,
+}
+
+impl Command {
+    // Order in this table is what gets saved, so only append to it
+    const ALL: [Command; 6] = [
+        Command::None,
+        Command::Pan(Pan::Left),
+        Command::Pan(Pan::Center),
+        Command::Pan(Pan::Right),
+        Command::Legato,
+        Command::Glide,
+    ];
+
+    fn position(&self) -> usize {
+        Self::ALL.iter().position(|a| a == self).unwrap_or(0)
+    }
+
+    pub fn next(&self) -> Self {
+        Self::ALL[(self.position() + 1).min(Self::ALL.len() - 1)]
+    }
+
+    pub fn prev(&self) -> Self {
+        Self::ALL[self.position().saturating_sub(1)]
+    }
+
+    pub fn to_nibble(self) -> u8 {
+        self.position() as u8
+    }
+
+    pub fn from_nibble(nibble: u8) -> Self {
+        *Self::ALL.get(nibble as usize).unwrap_or(&Command::None)
+    }
+
+    pub fn to_render(self) -> String {
+        match self {
+            Command::None => "--".to_string(),
+            Command::Pan(pan) => "P".to_string() + pan.to_render(),
+            Command::Legato => "LG".to_string(),
+            Command::Glide => "GL".to_string(),
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct Note {
     pub index: usize,
     pub instrument: usize,
+    pub command: Command,
+    // Two semitone offsets, high and low nibble, played after the note in turn
+    pub chord: u8,
 }
 
 impl Note {
@@ -35,38 +88,78 @@ impl Note {
         Note {
             index: NOTE_C3_INDEX,
             instrument: 0,
+            command: Command::None,
+            chord: 0,
         }
     }
 
-    pub fn increase_pitch(&mut self) {
-        if self.index < NOTE_FREQ.len() - 1 {
-            self.index += 1;
+    pub fn is_pitched(&self) -> bool {
+        self.index < NOTE_FREQ.len()
+    }
+
+    pub fn is_note_off(&self) -> bool {
+        self.index == NOTE_OFF_INDEX
+    }
+
+    pub fn is_note_cut(&self) -> bool {
+        self.index == NOTE_CUT_INDEX
+    }
+
+    // Going below the lowest pitch reaches note off and then note cut,
+    // pitches outside the scale are skipped
+    pub fn increase_pitch(&mut self, key: u8, scale: Scale) {
+        let next = match self.index {
+            NOTE_CUT_INDEX => Some(NOTE_OFF_INDEX),
+            NOTE_OFF_INDEX => scale.next_note(key, None),
+            index => scale.next_note(key, Some(index)),
+        };
+        if let Some(index) = next {
+            self.index = index
         }
     }
 
-    pub fn decrease_pitch(&mut self) {
-        if self.index > 0 {
-            self.index -= 1;
+    pub fn decrease_pitch(&mut self, key: u8, scale: Scale) {
+        match self.index {
+            NOTE_CUT_INDEX => {}
+            NOTE_OFF_INDEX => self.index = NOTE_CUT_INDEX,
+            index => self.index = scale.prev_note(key, index).unwrap_or(NOTE_OFF_INDEX),
         }
     }
 
     pub fn increase_octave(&mut self) {
+        if !self.is_pitched() {
+            return;
+        }
         let max_value: usize = NOTE_FREQ.len() - NOTES_PER_OCTAVE as usize;
         if self.index < max_value {
-            self.index = self.index + NOTES_PER_OCTAVE as usize;
+            self.index += NOTES_PER_OCTAVE as usize;
         } else {
-            self.index = NOTE_FREQ.len();
+            self.index = NOTE_FREQ.len() - 1;
         }
     }
 
     pub fn decrease_octave(&mut self) {
+        if !self.is_pitched() {
+            return;
+        }
         if (self.index as u32) >= NOTES_PER_OCTAVE {
-            self.index = self.index - NOTES_PER_OCTAVE as usize;
+            self.index -= NOTES_PER_OCTAVE as usize;
         } else {
             self.index = 0;
         }
     }
 
+    pub fn transposed(&self, semitones: i8) -> Note {
+        if !self.is_pitched() {
+            return *self;
+        }
+        let index = (self.index as i32 + semitones as i32).clamp(0, NOTE_FREQ.len() as i32 - 1);
+        Note {
+            index: index as usize,
+            ..*self
+        }
+    }
+
     pub fn next_instrument(&mut self) {
         if self.instrument < MAX_INSTRUMENTS - 1 {
             self.instrument += 1;
@@ -87,53 +180,82 @@ impl Note {
         self.index
     }
 
-    pub fn to_bytes(&self) -> (u8, u8) {
-        (self.index as u8, self.instrument as u8)
+    pub fn next_command(&mut self) {
+        self.command = self.command.next()
     }
-}
 
-const fn letter_to_note_num(letter: char) -> Option<usize> {
-    match letter {
-        'C' => Some(0),
-        'D' => Some(2),
-        'E' => Some(4),
-        'F' => Some(5),
-        'G' => Some(7),
-        'A' => Some(9),
-        'B' => Some(11),
-        _ => None,
+    pub fn prev_command(&mut self) {
+        self.command = self.command.prev()
+    }
+
+    pub fn command(&self) -> Command {
+        self.command
     }
-}
 
-pub fn note_from_string(name: &str) -> Option<usize> {
-    if name.len() < 2 {
-        return None;
+    pub fn chord(&self) -> u8 {
+        self.chord
     }
 
-    let note_num = if let Some(letter @ 'A'..='G') = name.chars().nth(0) {
-        letter_to_note_num(letter)? as u32
-    } else {
-        return None;
-    };
+    pub fn has_chord(&self) -> bool {
+        self.chord != 0
+    }
 
-    match (name.chars().nth(1), name.chars().nth(2)) {
-        (Some(octave @ '0'..='8'), None) => {
-            let octave_num = octave.to_digit(10)?;
-            let index = usize::try_from(NOTES_PER_OCTAVE * octave_num + note_num).ok()?;
-            Some(index)
+    // Semitones above the note at `frame`, cycling through 0, first and second offset
+    pub fn chord_offset(&self, frame: u32) -> u8 {
+        match frame % 3 {
+            0 => 0,
+            1 => self.chord >> 4,
+            _ => self.chord & 0x0f,
         }
-        (Some('#'), Some(octave @ '0'..='8')) => {
-            let octave_num = octave.to_digit(10)?;
-            let index = usize::try_from(NOTES_PER_OCTAVE * octave_num + note_num).ok()?;
-            Some(index + 1)
+    }
+
+    pub fn update_chord_first<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.chord = f(self.chord >> 4).min(0x0f) << 4 | self.chord & 0x0f
+    }
+
+    pub fn update_chord_second<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.chord = self.chord & 0xf0 | f(self.chord & 0x0f).min(0x0f)
+    }
+
+    // Note index takes 7 bits and instrument 5, the command nibble is spread over the rest
+    pub fn to_bytes(self) -> (u8, u8) {
+        let command = self.command.to_nibble();
+        (
+            self.index as u8 | (command >> 3) << 7,
+            self.instrument as u8 | (command & 0b111) << 5,
+        )
+    }
+
+    pub fn from_bytes(bytes: (u8, u8)) -> Self {
+        Note {
+            index: (bytes.0 & 0x7f).into(),
+            instrument: (bytes.1 & 0x1f).into(),
+            command: Command::from_nibble((bytes.0 >> 7) << 3 | bytes.1 >> 5),
+            chord: 0,
         }
-        _ => return None,
     }
 }
 
 pub fn note_to_render(note: usize) -> String {
+    match note {
+        NOTE_OFF_INDEX => return "OFF".to_string(),
+        NOTE_CUT_INDEX => return "CUT".to_string(),
+        _ => {}
+    }
     let octave = note / NOTES_PER_OCTAVE as usize;
-    let letter = match note % NOTES_PER_OCTAVE as usize {
+    let letter = pitch_class_to_render(note % NOTES_PER_OCTAVE as usize);
+    letter.to_string() + &decimal(octave as u32, 1)
+}
+
+// Note name without the octave, always two characters
+pub fn pitch_class_to_render(pitch_class: usize) -> &'static str {
+    match pitch_class {
         0 => "C-",
         1 => "C#",
         2 => "D-",
@@ -147,6 +269,5 @@ pub fn note_to_render(note: usize) -> String {
         10 => "A#",
         11 => "B-",
         _ => "--",
-    };
-    format!("{letter}{octave}")
+    }
 }
@@ -1,174 +1,306 @@
-use std::time::Duration;
-
 use crate::{
-    inputs::{InputEvent, Inputs},
-    navigation::{go_to_instrument_screen, go_to_song_screen},
+    controls::Action,
+    inputs::{
+        mouse,
+        Button::{Button1, Down, Left, Right, Up},
+        InputEvent, Inputs,
+    },
+    inputs_mut,
+    navigation::{add_header_handlers, go_to_instrument_screen, go_to_song_screen},
     notes::Note,
+    render::pattern_cell_at,
     screen::Screen,
-    timers::{ActionId, TIMERS},
-    tracker::{Column, PlayMode, TRACKER},
+    tracker::{tracker, Column, PlayMode},
 };
 
-fn on_button_down_press(inputs: &Inputs) {
-    unsafe {
-        if inputs.is_button1_pressed() && TRACKER.selected_column() == Column::Note {
-            TIMERS.run_action_debounced(
-                ActionId::PatternPitchOctaveDown,
-                Duration::from_millis(100),
-                || {
-                    if let Some(note) = TRACKER.current_note_mut() {
-                        note.decrease_octave();
-                    }
-                },
-            )
-        } else if inputs.is_button2_pressed() {
-            TIMERS.run_action_debounced(ActionId::Play, Duration::from_millis(200), || {
-                TRACKER.toggle_play(PlayMode::Pattern)
-            })
-        } else if inputs.is_button1_pressed() && TRACKER.selected_column() == Column::Instrument {
-        } else {
-            TIMERS.run_action_debounced(
-                ActionId::PatternNavDown,
-                Duration::from_millis(100),
-                || {
-                    TRACKER.saturating_increase_cursor_tick();
-                    if let Some(note) = TRACKER.current_note() {
-                        TRACKER.set_selected_instrument_index(note.instrument_index());
-                    }
-                },
-            )
-        }
-    }
-}
-
-fn on_button_up_press(inputs: &Inputs) {
-    unsafe {
-        if inputs.is_button1_pressed() && TRACKER.selected_column() == Column::Note {
-            TIMERS.run_action_debounced(
-                ActionId::PatternPitchOctaveUp,
-                Duration::from_millis(100),
-                || {
-                    if let Some(note) = TRACKER.current_note_mut() {
-                        note.increase_octave();
-                    }
-                },
-            )
-        } else if inputs.is_button1_pressed() && TRACKER.selected_column() == Column::Instrument {
-        } else if inputs.is_button2_pressed() {
-            TIMERS.run_action_debounced(ActionId::Persist, Duration::from_millis(1000), || {
-                TRACKER.persist();
-            })
-        } else {
-            TIMERS.run_action_debounced(ActionId::PatternNavUp, Duration::from_millis(100), || {
-                TRACKER.saturating_decrease_cursor_tick();
-                if let Some(note) = TRACKER.current_note() {
-                    TRACKER.set_selected_instrument_index(note.instrument_index());
-                }
-            })
+fn on_button_down_press(_inputs: &Inputs) {
+    unsafe {
+        tracker().saturating_increase_cursor_tick();
+        if let Some(note) = tracker().current_note() {
+            tracker().set_selected_instrument_index(note.instrument_index());
+        }
+    }
+}
+
+fn on_button_up_press(_inputs: &Inputs) {
+    unsafe {
+        tracker().saturating_decrease_cursor_tick();
+        if let Some(note) = tracker().current_note() {
+            tracker().set_selected_instrument_index(note.instrument_index());
         }
     }
 }
 
-fn on_button_1_press(_inputs: &Inputs) {
+fn on_button_right_press(_inputs: &Inputs) {
     unsafe {
-        if let None = TRACKER.current_note() {
+        match tracker().selected_column() {
+            Column::Note => tracker().set_selected_column(Column::Instrument),
+            Column::Instrument => tracker().set_selected_column(Column::Command),
+            Column::Command => tracker().set_selected_column(Column::Chord),
+            Column::Chord => {}
+        }
+    }
+}
+
+fn on_button_left_press(_inputs: &Inputs) {
+    unsafe {
+        match tracker().selected_column() {
+            Column::Note => {}
+            Column::Instrument => tracker().set_selected_column(Column::Note),
+            Column::Command => tracker().set_selected_column(Column::Instrument),
+            Column::Chord => tracker().set_selected_column(Column::Command),
+        }
+    }
+}
+
+fn on_add_note(_inputs: &Inputs) {
+    unsafe {
+        if tracker().current_note().is_none() {
             let new_note = Note::new();
-            TRACKER.set_current_note(&Some(new_note));
-        }
-    }
-}
-
-fn on_button_1_double_press(_inputs: &Inputs) {
-    unsafe {
-        if let Some(_) = TRACKER.current_note() {
-            TRACKER.set_current_note(&None);
-        }
-    }
-}
-
-fn on_button_right_press(inputs: &Inputs) {
-    unsafe {
-        if inputs.is_button1_pressed() {
-            match TRACKER.selected_column() {
-                Column::Note => TIMERS.run_action_debounced(
-                    ActionId::PatternPitchUp,
-                    Duration::from_millis(100),
-                    || {
-                        if let Some(note) = TRACKER.current_note_mut() {
-                            note.increase_pitch();
-                        }
-                    },
-                ),
-                Column::Instrument => TIMERS.run_action_debounced(
-                    ActionId::PatternInstrumentNext,
-                    Duration::from_millis(200),
-                    || {
-                        if let Some(note) = TRACKER.current_note_mut() {
-                            note.next_instrument();
-                        }
-                    },
-                ),
-            };
-        } else if inputs.is_button2_pressed() {
-            TIMERS.run_action_debounced(
-                ActionId::NavNextScreen,
-                Duration::from_millis(200),
-                || {
-                    if let Some(note) = TRACKER.current_note() {
-                        TRACKER.set_selected_instrument_index(note.instrument_index());
-                    }
-                    go_to_instrument_screen(Screen::Pattern);
-                },
-            );
-        } else if TRACKER.selected_column() == Column::Note {
-            TRACKER.set_selected_column(Column::Instrument);
-        }
-    }
-}
-
-fn on_button_left_press(inputs: &Inputs) {
-    unsafe {
-        if inputs.is_button1_pressed() {
-            match TRACKER.selected_column() {
-                Column::Note => TIMERS.run_action_debounced(
-                    ActionId::PatternPitchDown,
-                    Duration::from_millis(100),
-                    || {
-                        if let Some(note) = TRACKER.current_note_mut() {
-                            note.decrease_pitch()
-                        }
-                    },
-                ),
-                Column::Instrument => TIMERS.run_action_debounced(
-                    ActionId::PatternInstrumentPrev,
-                    Duration::from_millis(200),
-                    || {
-                        if let Some(note) = TRACKER.current_note_mut() {
-                            note.prev_instrument()
-                        }
-                    },
-                ),
+            tracker().set_current_note(&Some(new_note));
+            tracker().preview_current_note();
+        }
+    }
+}
+
+fn on_remove_note(_inputs: &Inputs) {
+    unsafe {
+        if tracker().current_note().is_some() {
+            tracker().set_current_note(&None);
+        }
+    }
+}
+
+fn on_button_1_down_press(_inputs: &Inputs) {
+    unsafe {
+        let before = *tracker().current_note();
+        if let Some(note) = tracker().current_note_mut() {
+            match tracker().selected_column() {
+                Column::Note => note.decrease_octave(),
+                Column::Chord => note.update_chord_first(|a| a.saturating_sub(1)),
+                Column::Instrument | Column::Command => {}
+            }
+        }
+        tracker().fit_player_note(0, before);
+        tracker().preview_current_note();
+    }
+}
+
+fn on_button_1_up_press(_inputs: &Inputs) {
+    unsafe {
+        let before = *tracker().current_note();
+        if let Some(note) = tracker().current_note_mut() {
+            match tracker().selected_column() {
+                Column::Note => note.increase_octave(),
+                Column::Chord => note.update_chord_first(|a| a.saturating_add(1)),
+                Column::Instrument | Column::Command => {}
+            }
+        }
+        tracker().fit_player_note(0, before);
+        tracker().preview_current_note();
+    }
+}
+
+fn on_button_1_right_press(_inputs: &Inputs) {
+    unsafe {
+        let before = *tracker().current_note();
+        if let Some(note) = tracker().current_note_mut() {
+            match tracker().selected_column() {
+                Column::Note => {
+                    let scale = tracker().scale_for(*note, tracker().selected_channel());
+                    note.increase_pitch(tracker().key(), scale)
+                }
+                Column::Instrument => note.next_instrument(),
+                Column::Command => note.next_command(),
+                Column::Chord => note.update_chord_second(|a| a.saturating_add(1)),
+            }
+        }
+        tracker().fit_player_note(0, before);
+        tracker().preview_current_note();
+    }
+}
+
+fn on_button_1_left_press(_inputs: &Inputs) {
+    unsafe {
+        let before = *tracker().current_note();
+        if let Some(note) = tracker().current_note_mut() {
+            match tracker().selected_column() {
+                Column::Note => {
+                    let scale = tracker().scale_for(*note, tracker().selected_channel());
+                    note.decrease_pitch(tracker().key(), scale)
+                }
+                Column::Instrument => note.prev_instrument(),
+                Column::Command => note.prev_command(),
+                Column::Chord => note.update_chord_second(|a| a.saturating_sub(1)),
+            }
+        }
+        tracker().fit_player_note(0, before);
+        tracker().preview_current_note();
+    }
+}
+
+fn on_play(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            let recording = tracker().is_recording();
+            tracker().toggle_play(PlayMode::Pattern);
+            // Stopping the playback also stops recording
+            if recording {
+                reset_handlers();
+            }
+        }
+    }
+}
+
+fn on_record(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().toggle_recording();
+            reset_handlers();
+        }
+    }
+}
+
+// The d-pad plays notes while recording and moves the cursor otherwise
+unsafe fn reset_handlers() {
+    inputs_mut()[0].unlisten();
+    add_pattern_screen_handlers(&mut inputs_mut()[0]);
+}
+
+unsafe fn preview_record_note() {
+    tracker().preview_note(tracker().record_note(), tracker().selected_channel());
+}
+
+fn on_record_up_press(_inputs: &Inputs) {
+    unsafe {
+        tracker().step_record_note(1);
+        preview_record_note();
+    }
+}
+
+fn on_record_down_press(_inputs: &Inputs) {
+    unsafe {
+        tracker().step_record_note(-1);
+        preview_record_note();
+    }
+}
+
+fn on_record_right_press(_inputs: &Inputs) {
+    unsafe {
+        tracker().step_record_octave(true);
+        preview_record_note();
+    }
+}
+
+fn on_record_left_press(_inputs: &Inputs) {
+    unsafe {
+        tracker().step_record_octave(false);
+        preview_record_note();
+    }
+}
+
+fn on_record_add_note(_inputs: &Inputs) {
+    unsafe { tracker().commit_record_note() }
+}
+
+fn on_save(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().persist();
+        }
+    }
+}
+
+fn on_next_screen(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            if let Some(note) = tracker().current_note() {
+                tracker().set_selected_instrument_index(note.instrument_index());
             }
-        } else if inputs.is_button2_pressed() {
-            TIMERS.run_action_debounced(
-                ActionId::NavPrevScreen,
-                Duration::from_millis(200),
-                || {
-                    go_to_song_screen(Screen::Pattern);
-                },
-            );
-        } else if TRACKER.selected_column() == Column::Instrument {
-            TRACKER.set_selected_column(Column::Note);
+            go_to_instrument_screen(Screen::Pattern);
         }
     }
 }
 
+fn on_prev_screen(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            go_to_song_screen(Screen::Pattern);
+        }
+    }
+}
+
+unsafe fn move_cursor_to(tick: u8) {
+    tracker().set_cursor_tick(tick);
+    if let Some(note) = tracker().current_note() {
+        tracker().set_selected_instrument_index(note.instrument_index());
+    }
+}
+
+fn on_mouse_click(_inputs: &Inputs) {
+    let (x, y) = mouse().position();
+    if let Some((tick, column)) = pattern_cell_at(x, y) {
+        unsafe {
+            move_cursor_to(tick);
+            tracker().set_selected_column(column);
+        }
+    }
+}
+
+fn on_mouse_right_click(_inputs: &Inputs) {
+    let (x, y) = mouse().position();
+    if let Some((tick, _)) = pattern_cell_at(x, y) {
+        unsafe {
+            move_cursor_to(tick);
+            tracker().set_current_note(&None);
+        }
+    }
+}
+
+fn on_mouse_wheel(_inputs: &Inputs) {
+    unsafe {
+        let tick = i32::from(tracker().cursor_tick()) + mouse().wheel_steps();
+        move_cursor_to(tick.clamp(0, 15) as u8);
+    }
+}
+
+fn add_record_handlers(inputs: &mut Inputs) {
+    let bindings = unsafe { *tracker().bindings() };
+    inputs
+        .listen(InputEvent::Press(Up), on_record_up_press)
+        .listen(InputEvent::Press(Down), on_record_down_press)
+        .listen(InputEvent::Press(Right), on_record_right_press)
+        .listen(InputEvent::Press(Left), on_record_left_press)
+        .listen(bindings.event(Action::AddNote), on_record_add_note)
+        .listen(bindings.event(Action::Play), on_play)
+        .listen(bindings.event(Action::Save), on_save)
+        .listen(bindings.event(Action::Record), on_record);
+}
+
 pub fn add_pattern_screen_handlers(inputs: &mut Inputs) {
+    if unsafe { tracker().is_recording() } {
+        add_record_handlers(inputs);
+        return;
+    }
+    let bindings = unsafe { *tracker().bindings() };
     inputs
-        .listen(InputEvent::ButtonDownPress, on_button_down_press)
-        .listen(InputEvent::ButtonUpPress, on_button_up_press)
-        .listen(InputEvent::Button1Press, on_button_1_press)
-        .listen(InputEvent::Button1DoublePress, on_button_1_double_press)
-        .listen(InputEvent::ButtonRightPress, on_button_right_press)
-        .listen(InputEvent::ButtonLeftPress, on_button_left_press);
+        .listen(InputEvent::Press(Down), on_button_down_press)
+        .listen(InputEvent::Press(Up), on_button_up_press)
+        .listen(InputEvent::Press(Right), on_button_right_press)
+        .listen(InputEvent::Press(Left), on_button_left_press)
+        .listen(bindings.event(Action::AddNote), on_add_note)
+        .listen(bindings.event(Action::RemoveNote), on_remove_note)
+        .listen(InputEvent::Combo(Button1, Down), on_button_1_down_press)
+        .listen(InputEvent::Combo(Button1, Up), on_button_1_up_press)
+        .listen(InputEvent::Combo(Button1, Right), on_button_1_right_press)
+        .listen(InputEvent::Combo(Button1, Left), on_button_1_left_press)
+        .listen(bindings.event(Action::Play), on_play)
+        .listen(bindings.event(Action::Save), on_save)
+        .listen(bindings.event(Action::NextScreen), on_next_screen)
+        .listen(bindings.event(Action::PrevScreen), on_prev_screen)
+        .listen(bindings.event(Action::Record), on_record)
+        .listen(InputEvent::MouseClick, on_mouse_click)
+        .listen(InputEvent::MouseRightClick, on_mouse_right_click)
+        .listen(InputEvent::MouseWheel, on_mouse_wheel);
+    add_header_handlers(inputs);
 }
@@ -0,0 +1,124 @@
+use crate::{
+    controls::Action,
+    inputs::{
+        mouse,
+        Button::{Button1, Down, Right, Up},
+        InputEvent, Inputs,
+    },
+    navigation::{
+        add_header_handlers, go_to_pattern_screen, go_to_song_screen, go_to_song_settings_screen,
+    },
+    render::pattern_list_index_at,
+    screen::Screen,
+    tracker::{tracker, PlayMode},
+};
+
+fn on_button_down_press(_inputs: &Inputs) {
+    unsafe { tracker().next_selected_pattern() }
+}
+
+fn on_button_up_press(_inputs: &Inputs) {
+    unsafe { tracker().prev_selected_pattern() }
+}
+
+const CLEAR_PATTERN_NOTICE: &[u8] = b"Clear pattern? \x80+\x87";
+
+// Clearing can't be undone, so it takes a second press while asked
+fn on_button_1_down_press(inputs: &Inputs) {
+    unsafe {
+        if inputs.is_repeat() {
+            return;
+        }
+        if tracker().notice() == Some(CLEAR_PATTERN_NOTICE) {
+            tracker().clear_pattern(tracker().selected_pattern());
+            tracker().notify(b"Pattern cleared");
+        } else {
+            tracker().notify(CLEAR_PATTERN_NOTICE);
+        }
+    }
+}
+
+fn on_button_1_up_press(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            if let Some(clone_index) = tracker().clone_pattern(tracker().selected_pattern()) {
+                tracker().set_selected_pattern(clone_index);
+            }
+        }
+    }
+}
+
+fn on_button_1_right_press(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            go_to_pattern_screen(Screen::PatternList);
+        }
+    }
+}
+
+fn on_play(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().toggle_play(PlayMode::Pattern)
+        }
+    }
+}
+
+fn on_save(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().persist();
+        }
+    }
+}
+
+fn on_next_screen(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            go_to_song_screen(Screen::PatternList);
+        }
+    }
+}
+
+fn on_prev_screen(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            go_to_song_settings_screen(Screen::PatternList);
+        }
+    }
+}
+
+fn on_mouse_click(_inputs: &Inputs) {
+    let (_, y) = mouse().position();
+    if let Some(index) = pattern_list_index_at(y) {
+        unsafe { tracker().set_selected_pattern(index) }
+    }
+}
+
+fn on_mouse_wheel(_inputs: &Inputs) {
+    unsafe {
+        for _ in 0..mouse().wheel_steps() {
+            tracker().next_selected_pattern()
+        }
+        for _ in mouse().wheel_steps()..0 {
+            tracker().prev_selected_pattern()
+        }
+    }
+}
+
+pub fn add_pattern_list_screen_handlers(inputs: &mut Inputs) {
+    let bindings = unsafe { *tracker().bindings() };
+    inputs
+        .listen(InputEvent::Press(Down), on_button_down_press)
+        .listen(InputEvent::Press(Up), on_button_up_press)
+        .listen(InputEvent::Combo(Button1, Down), on_button_1_down_press)
+        .listen(InputEvent::Combo(Button1, Up), on_button_1_up_press)
+        .listen(InputEvent::Combo(Button1, Right), on_button_1_right_press)
+        .listen(bindings.event(Action::Play), on_play)
+        .listen(bindings.event(Action::Save), on_save)
+        .listen(bindings.event(Action::NextScreen), on_next_screen)
+        .listen(bindings.event(Action::PrevScreen), on_prev_screen)
+        .listen(InputEvent::MouseClick, on_mouse_click)
+        .listen(InputEvent::MouseWheel, on_mouse_wheel);
+    add_header_handlers(inputs);
+}
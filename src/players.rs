@@ -0,0 +1,199 @@
+use crate::{
+    channel::Channel,
+    controls::Action,
+    inputs::{
+        Button::{Button1, Down, Left, Right, Up},
+        InputEvent, Inputs,
+    },
+    inputs_mut,
+    notes::Note,
+    tracker::tracker,
+};
+
+// Every gamepad edits the song on its own channel, the first one also drives
+// the screens and gets their handlers instead of the ones below
+pub const PLAYERS: usize = 4;
+
+// A player's cursor is a step in the pattern of their channel on the song cursor row
+#[derive(Clone, Copy)]
+pub struct Player {
+    active: bool,
+    channel: Channel,
+    tick: u8,
+}
+
+impl Player {
+    pub const fn new(channel: Channel) -> Self {
+        Player {
+            active: false,
+            channel,
+            tick: 0,
+        }
+    }
+
+    // The first gamepad is always there
+    pub const fn first() -> Self {
+        Player {
+            active: true,
+            ..Player::new(Channel::Pulse1)
+        }
+    }
+
+    // Players show up once they press a button
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    pub fn tick(&self) -> u8 {
+        self.tick
+    }
+
+    pub fn set_tick(&mut self, tick: u8) {
+        self.tick = tick.min(15)
+    }
+
+    pub fn set_channel(&mut self, channel: Channel) {
+        self.channel = channel
+    }
+
+    pub fn saturating_increase_tick(&mut self) {
+        if self.tick < 15 {
+            self.tick += 1
+        }
+    }
+
+    pub fn saturating_decrease_tick(&mut self) {
+        if self.tick != 0 {
+            self.tick -= 1
+        }
+    }
+}
+
+unsafe fn update_note<F>(player: usize, f: F)
+where
+    F: FnOnce(&mut Note),
+{
+    if let Some(Some(note)) = tracker().player_note_mut(player) {
+        f(note);
+        let note = *note;
+        tracker().preview_note(note, tracker().players()[player].channel());
+    }
+}
+
+fn on_button_down_press(inputs: &Inputs) {
+    unsafe {
+        tracker()
+            .player_mut(inputs.player())
+            .saturating_increase_tick()
+    }
+}
+
+fn on_button_up_press(inputs: &Inputs) {
+    unsafe {
+        tracker()
+            .player_mut(inputs.player())
+            .saturating_decrease_tick()
+    }
+}
+
+fn on_add_note(inputs: &Inputs) {
+    let player = inputs.player();
+    unsafe {
+        if let Some(note @ None) = tracker().player_note_mut(player) {
+            *note = Some(Note::new());
+            tracker().fit_player_note(player, None);
+            tracker().preview_note(Note::new(), tracker().players()[player].channel());
+        }
+    }
+}
+
+fn on_remove_note(inputs: &Inputs) {
+    unsafe {
+        if let Some(note) = tracker().player_note_mut(inputs.player()) {
+            *note = None;
+        }
+    }
+}
+
+fn on_button_1_down_press(inputs: &Inputs) {
+    unsafe { update_note(inputs.player(), Note::decrease_octave) }
+}
+
+fn on_button_1_up_press(inputs: &Inputs) {
+    unsafe { update_note(inputs.player(), Note::increase_octave) }
+}
+
+fn on_button_1_left_press(inputs: &Inputs) {
+    let player = inputs.player();
+    unsafe {
+        let channel = tracker().players()[player].channel();
+        update_note(player, |note| {
+            note.decrease_pitch(tracker().key(), tracker().scale_for(*note, channel))
+        })
+    }
+}
+
+fn on_button_1_right_press(inputs: &Inputs) {
+    let player = inputs.player();
+    unsafe {
+        let channel = tracker().players()[player].channel();
+        update_note(player, |note| {
+            note.increase_pitch(tracker().key(), tracker().scale_for(*note, channel))
+        })
+    }
+}
+
+// Players have no screens of their own, the screen actions move them between channels
+fn on_prev_channel(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            let player = tracker().player_mut(inputs.player());
+            player.channel = player.channel.prev();
+        }
+    }
+}
+
+fn on_next_channel(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            let player = tracker().player_mut(inputs.player());
+            player.channel = player.channel.next();
+        }
+    }
+}
+
+fn add_player_handlers(inputs: &mut Inputs) {
+    let bindings = unsafe { *tracker().bindings() };
+    inputs
+        .listen(InputEvent::Press(Down), on_button_down_press)
+        .listen(InputEvent::Press(Up), on_button_up_press)
+        .listen(bindings.event(Action::AddNote), on_add_note)
+        .listen(bindings.event(Action::RemoveNote), on_remove_note)
+        .listen(InputEvent::Combo(Button1, Down), on_button_1_down_press)
+        .listen(InputEvent::Combo(Button1, Up), on_button_1_up_press)
+        .listen(InputEvent::Combo(Button1, Left), on_button_1_left_press)
+        .listen(InputEvent::Combo(Button1, Right), on_button_1_right_press)
+        .listen(bindings.event(Action::PrevScreen), on_prev_channel)
+        .listen(bindings.event(Action::NextScreen), on_next_channel);
+}
+
+// Also called after rebinding, the players share the binding table
+pub unsafe fn reset_player_handlers() {
+    for inputs in inputs_mut().iter_mut().skip(1) {
+        inputs.unlisten();
+        add_player_handlers(inputs);
+    }
+}
+
+pub unsafe fn tick_players() {
+    for inputs in inputs_mut().iter_mut() {
+        inputs.tick();
+        if inputs.is_any_held() {
+            tracker().player_mut(inputs.player()).active = true;
+        }
+    }
+}
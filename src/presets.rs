@@ -0,0 +1,22 @@
+use crate::instrument::{DutyCycle, Instrument};
+
+pub static PRESETS: [Instrument; 13] = [
+    // triangle
+    Instrument::new(DutyCycle::Eighth, 0, 6, 0, 4, 0x64, 0x64, -24).named(b"KICK  "),
+    Instrument::new(DutyCycle::Eighth, 0, 8, 0, 6, 0x5a, 0x64, -7).named(b"TOM   "),
+    Instrument::new(DutyCycle::Eighth, 0, 10, 4, 4, 0x50, 0x64, 0).named(b"BASS  "),
+    // noise
+    Instrument::new(DutyCycle::Eighth, 0, 8, 0, 6, 0x3c, 0x64, -6).named(b"SNARE "),
+    Instrument::new(DutyCycle::Eighth, 0, 2, 0, 3, 0x28, 0x3c, 0).named(b"HAT   "),
+    Instrument::new(DutyCycle::Eighth, 0, 4, 2, 10, 0x28, 0x3c, 0).named(b"OPNHAT"),
+    Instrument::new(DutyCycle::Eighth, 0, 10, 10, 30, 0x1e, 0x50, -2).named(b"CRASH "),
+    Instrument::new(DutyCycle::Eighth, 0, 0, 0, 0, 0x64, 0x64, 0)
+        .named(b"DRUMS ")
+        .as_drum_kit(),
+    // pulse
+    Instrument::new(DutyCycle::Half, 0, 8, 0, 6, 0x3c, 0x64, 0).named(b"PLUCK "),
+    Instrument::new(DutyCycle::Fourth, 2, 6, 16, 8, 0x32, 0x46, 0).named(b"LEAD  "),
+    Instrument::new(DutyCycle::Half, 20, 10, 30, 30, 0x28, 0x32, 0).named(b"PAD   "),
+    Instrument::new(DutyCycle::Eighth, 0, 3, 0, 2, 0x32, 0x50, 0).named(b"BLIP  "),
+    Instrument::new(DutyCycle::Fourth, 0, 10, 0, 4, 0x32, 0x64, 24).named(b"ZAP   "),
+];
@@ -1,10 +1,17 @@
 use crate::{
     channel::Channel,
-    instrument::{DutyCycle, InstrumentInput},
-    notes::note_to_render,
+    controls::{Action, ControlsInput},
+    digits::{decimal, hex, signed_decimal},
+    instrument::{
+        DutyCycle, Instrument, InstrumentInput, INSTRUMENT_NAME_LENGTH, LFO_PEAK, MAX_INSTRUMENTS,
+    },
+    macros::{MacroKind, MAX_SEQUENCE_LENGTH},
+    notes::{note_to_render, pitch_class_to_render, NOTES_PER_OCTAVE, NOTE_C3_INDEX, NOTE_FREQ},
     screen::{Screen, Screens},
-    tracker::{Column, PlayMode, Tracker},
-    wasm4::{hline, rect, text, text_bytes, vline, DRAW_COLORS, SCREEN_SIZE},
+    song_settings::SongSettingsInput,
+    tracker::{Column, PlayMode, SongColumn, Tracker, MAX_PATTERNS},
+    wasm4::{hline, line, rect, text, text_bytes, vline, DRAW_COLORS, SCREEN_SIZE},
+    wtime::duration_to_render,
 };
 
 enum Color {
@@ -25,6 +32,66 @@ fn set_color(color: Color) {
     }
 }
 
+// Inverted in a box `height` high when selected, leaves the primary color set
+fn value_text(value: &[u8], x: i32, y: i32, height: u32, selected: bool) {
+    if selected {
+        set_color(Color::Primary);
+        rect(x - 1, y - 1, 8 * value.len() as u32 + 1, height);
+        set_color(Color::Background);
+    }
+    text_bytes(value, x, y);
+    set_color(Color::Primary);
+}
+
+// Draws only the outline of the following rects
+fn set_outline_color(color: Color) {
+    set_color(color);
+    unsafe { *DRAW_COLORS <<= 4 }
+}
+
+// The first gamepad keeps the primary color
+fn player_color(player: usize) -> Color {
+    match player {
+        1 => Color::Dark,
+        2 => Color::Light,
+        _ => Color::Primary,
+    }
+}
+
+// The first player's cursor is filled, the others get an outline in their color.
+// The fourth player shares the first one's color, their outline only has corners
+fn player_outline(player: usize, x: i32, y: i32, width: u32, height: u32) {
+    if player < 3 {
+        set_outline_color(player_color(player));
+        rect(x, y, width, height);
+        return;
+    }
+    set_color(player_color(player));
+    let (right, bottom) = (x + width as i32 - 1, y + height as i32 - 1);
+    for (corner_x, corner_y) in [(x, y), (right - 2, y), (x, bottom), (right - 2, bottom)] {
+        hline(corner_x, corner_y, 3);
+    }
+    for (corner_x, corner_y) in [(x, y), (right, y), (x, bottom - 2), (right, bottom - 2)] {
+        vline(corner_x, corner_y, 3);
+    }
+}
+
+// Pattern step and column under a mouse position
+pub fn pattern_cell_at(x: i32, y: i32) -> Option<(u8, Column)> {
+    let line = y / 10;
+    if !(0..16).contains(&line) {
+        return None;
+    }
+    let column = match x {
+        20..=45 => Column::Note,
+        46..=64 => Column::Instrument,
+        65..=83 => Column::Command,
+        84..=101 => Column::Chord,
+        _ => return None,
+    };
+    Some((line as u8, column))
+}
+
 pub fn pattern_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
     let relative_x = |a: i32| a + origin_x;
     let relative_y = |a: i32| a + origin_y;
@@ -34,86 +101,159 @@ pub fn pattern_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
 
     let pattern = tracker.selected_pattern();
     text(
-        format!("Pattern {:02X}", pattern),
-        relative_x(80),
+        "Pattern\n".to_string() + &hex(pattern as u32, 2),
+        relative_x(104),
         relative_y(1),
     );
 
+    let drum_kit_channel = tracker.selected_channel() == Channel::Noise;
+
     for line in 0..16 {
         text(
-            format!("{:0X}", line),
+            hex(line as u32, 1),
             relative_x(1),
             relative_y(line * 10 + 1),
         );
 
         let note = tracker.note_at(line as usize);
         let name = if let Some(note) = note {
-            note_to_render(usize::from(note.note_index()))
+            match tracker.note_drum(note) {
+                Some(drum) if drum_kit_channel && note.is_pitched() => drum.name().to_string(),
+                _ => note_to_render(note.note_index()),
+            }
         } else {
             "---".to_string()
         };
 
+        // Notes outside the song scale are underlined
+        let out_of_scale = note.is_some_and(|a| {
+            a.is_pitched()
+                && !tracker
+                    .scale_for(a, tracker.selected_channel())
+                    .contains(tracker.key(), a.note_index())
+        });
         if line == cursor.into() && selected_column == Column::Note {
             rect(relative_x(20), relative_y(line * 10), 8 * 3 + 1, 10);
             set_color(Color::Background);
             text(name, relative_x(21), relative_y(line * 10 + 1));
-            set_color(Color::Primary);
         } else {
             text(name, relative_x(21), relative_y(line * 10 + 1));
+            set_color(Color::Dark);
         };
+        if out_of_scale {
+            hline(relative_x(21), relative_y(line * 10 + 9), 8 * 3);
+        }
+        set_color(Color::Primary);
 
         let instrument_name = if let Some(note) = note {
-            format!("{:02X}", note.instrument_index())
+            hex(note.instrument_index() as u32, 2)
         } else {
             "--".to_string()
         };
-        if line == cursor.into() && selected_column == Column::Instrument {
-            rect(relative_x(50), relative_y(line * 10), 8 * 2 + 1, 10);
-            set_color(Color::Background);
-            text(instrument_name, relative_x(51), relative_y(line * 10 + 1));
-            set_color(Color::Primary);
+        value_text(
+            instrument_name.as_bytes(),
+            relative_x(48),
+            relative_y(line * 10 + 1),
+            10,
+            line == cursor.into() && selected_column == Column::Instrument,
+        );
+
+        let command_name = if let Some(note) = note {
+            note.command().to_render()
         } else {
-            text(instrument_name, relative_x(51), relative_y(line * 10 + 1));
+            "--".to_string()
         };
+        value_text(
+            command_name.as_bytes(),
+            relative_x(66),
+            relative_y(line * 10 + 1),
+            10,
+            line == cursor.into() && selected_column == Column::Command,
+        );
+
+        let chord_name = match note {
+            Some(note) if note.has_chord() => hex(note.chord() as u32, 2),
+            _ => "--".to_string(),
+        };
+        value_text(
+            chord_name.as_bytes(),
+            relative_x(85),
+            relative_y(line * 10 + 1),
+            10,
+            line == cursor.into() && selected_column == Column::Chord,
+        );
+    }
+
+    if let Some(note) = tracker.current_note() {
+        let instrument = tracker.instrument(note.instrument_index());
+        text(
+            hex(note.instrument_index() as u32, 2) + "\n" + instrument.name(),
+            relative_x(104),
+            relative_y(140),
+        );
+    }
+
+    // The first player is the cursor drawn above
+    for (player, state) in tracker.players().iter().enumerate().skip(1) {
+        if !state.is_active() {
+            continue;
+        }
+        let pattern = tracker.player_pattern(player);
+        let tick = i32::from(state.tick());
+        if pattern == Some(tracker.selected_pattern()) {
+            player_outline(player, relative_x(19), relative_y(tick * 10), 27, 10);
+        }
+        let note = match pattern.and_then(|a| tracker.pattern(a)[tick as usize]) {
+            Some(note) => note_to_render(note.note_index()),
+            None => "---".to_string(),
+        };
+        set_color(player_color(player));
+        text(
+            decimal(player as u32 + 1, 1) + state.channel().to_render() + " " + &note,
+            relative_x(104),
+            relative_y(10 + player as i32 * 10),
+        );
+    }
+    set_color(Color::Primary);
+
+    if tracker.is_recording() {
+        let note = tracker.record_note().note_index();
+        text(
+            "REC ".to_string() + &note_to_render(note),
+            relative_x(104),
+            relative_y(48),
+        );
     }
 
     set_color(Color::Light);
-    let first_row_y = 88;
-    text_bytes(
-        b"nav:   \x84\x85\x86\x87",
-        relative_x(70),
-        relative_y(first_row_y + 10 * 0),
-    );
-    text_bytes(
-        b"play:   \x81+\x87",
-        relative_x(70),
-        relative_y(first_row_y + 10 * 1),
-    );
-    text_bytes(
-        b"add note: \x80",
-        relative_x(70),
-        relative_y(first_row_y + 10 * 2),
-    );
-    text_bytes(
-        b"rm note: \x80\x80",
-        relative_x(70),
-        relative_y(first_row_y + 10 * 3),
-    );
-    text_bytes(
-        b"edit:\x80+\x84\x85\x86\x87",
-        relative_x(70),
-        relative_y(first_row_y + 10 * 4),
-    );
-    text_bytes(
-        b"screen:\x81+\x84\x85",
-        relative_x(70),
-        relative_y(first_row_y + 10 * 5),
-    );
-    text_bytes(
-        b"save:   \x81+\x86",
-        relative_x(70),
-        relative_y(first_row_y + 10 * 6),
-    );
+    let first_row_y = if tracker.is_recording() { 58 } else { 48 };
+    let (mut help, actions): (Vec<Vec<u8>>, &[Action]) = if tracker.is_recording() {
+        (
+            vec![b"deg  \x86\x87".to_vec(), b"oct  \x84\x85".to_vec()],
+            // The note is put with whatever adds notes outside recording
+            &[Action::AddNote, Action::Play, Action::Save, Action::Record],
+        )
+    } else {
+        (
+            vec![b"nav\x84\x85\x86\x87".to_vec(), b"edit \x80+".to_vec()],
+            &Action::ALL,
+        )
+    };
+    // Labels on the left, bound buttons on the right of a 7 character line
+    for &action in actions {
+        let glyphs = tracker.bindings().event(action).to_render();
+        let mut line = action.to_help().as_bytes().to_vec();
+        line.resize(7 - glyphs.len(), b' ');
+        line.extend(glyphs);
+        help.push(line);
+    }
+    for (row, line) in help.iter().enumerate() {
+        text_bytes(
+            line,
+            relative_x(104),
+            relative_y(first_row_y + 10 * row as i32),
+        );
+    }
 
     set_color(Color::Primary);
 
@@ -131,6 +271,294 @@ fn draw_sqr_waveform(signal_active: u32, signal_width: u32, amplitude: u32, x: i
     );
 }
 
+fn duty_cycle_active_width(duty_cycle: DutyCycle, signal_width: u32) -> u32 {
+    match duty_cycle {
+        DutyCycle::Eighth => signal_width / 8,
+        DutyCycle::Fourth => signal_width / 4,
+        DutyCycle::Half => signal_width / 2,
+        DutyCycle::ThreeFourth => 3 * signal_width / 4,
+    }
+}
+
+fn draw_envelope(instrument: &Instrument, x: i32, y: i32, width: u32, height: u32) {
+    let attack = instrument.attack() as u32;
+    let decay = instrument.decay() as u32;
+    let sustain = instrument.sustain() as u32;
+    let release = instrument.release() as u32;
+    let total = (attack + decay + sustain + release).max(1);
+    let to_x = |frames: u32| x + (frames * width / total) as i32;
+
+    // wasm-4 treats zero peak as full volume
+    let peak = if instrument.peak() == 0 {
+        100
+    } else {
+        instrument.peak() as u32
+    };
+    let to_y = |level: u32| y + height as i32 - (level.min(100) * height / 100) as i32;
+
+    let bottom = to_y(0);
+    let peak_y = to_y(peak);
+    let sustain_y = to_y(instrument.volume() as u32);
+    let attack_end = to_x(attack);
+    let decay_end = to_x(attack + decay);
+    let sustain_end = to_x(attack + decay + sustain);
+
+    line(x, bottom, attack_end, peak_y);
+    line(attack_end, peak_y, decay_end, sustain_y);
+    line(decay_end, sustain_y, sustain_end, sustain_y);
+    line(sustain_end, sustain_y, to_x(total), bottom);
+}
+
+// Macro kind and step under a mouse position
+pub fn macro_cell_at(x: i32, y: i32) -> Option<(MacroKind, usize)> {
+    let step = (x - 28) / 8;
+    if x < 28 || step >= MAX_SEQUENCE_LENGTH as i32 || !(12..12 + 4 * 36).contains(&y) {
+        return None;
+    }
+    Some((
+        MacroKind::from_index(((y - 12) / 36) as usize),
+        step as usize,
+    ))
+}
+
+pub fn macro_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
+    let relative_x = |a: i32| a + origin_x;
+    let relative_y = |a: i32| a + origin_y;
+
+    let instrument = tracker.selected_instrument();
+    let cursor_kind = tracker.macro_cursor_kind();
+    let cursor_step = tracker.macro_cursor_step();
+
+    set_color(Color::Primary);
+    text(
+        "Macros ".to_string()
+            + &hex(tracker.selected_instrument_index() as u32, 2)
+            + " "
+            + instrument.name(),
+        relative_x(1),
+        relative_y(1),
+    );
+    if let Some(value) = instrument.macro_sequence(cursor_kind).value(cursor_step) {
+        text(signed_decimal(value.into()), relative_x(128), relative_y(1));
+    }
+
+    let graph_x = 28;
+    let step_width = 8;
+    let graph_height = 24;
+    for kind in MacroKind::iterator() {
+        let top = 12 + kind.index() as i32 * 36;
+        let sequence = instrument.macro_sequence(kind);
+
+        if kind == cursor_kind {
+            rect(relative_x(0), relative_y(top + 7), 8 * 3 + 2, 10);
+            set_color(Color::Background);
+            text(kind.label(), relative_x(1), relative_y(top + 8));
+            set_color(Color::Light);
+            rect(
+                relative_x(graph_x + cursor_step as i32 * step_width),
+                relative_y(top),
+                step_width as u32,
+                graph_height as u32,
+            );
+        } else {
+            set_color(Color::Primary);
+            text(kind.label(), relative_x(1), relative_y(top + 8));
+        }
+
+        let (min, max) = kind.range();
+        let max_abs = max.max(-min) as i32;
+        let zero_y = if min < 0 {
+            top + graph_height / 2
+        } else {
+            top + graph_height
+        };
+        let scale = if min < 0 {
+            graph_height / 2
+        } else {
+            graph_height
+        };
+        set_color(Color::Light);
+        hline(
+            relative_x(graph_x),
+            relative_y(zero_y),
+            (MAX_SEQUENCE_LENGTH as i32 * step_width) as u32,
+        );
+
+        for step in 0..sequence.len() {
+            let x = graph_x + step as i32 * step_width;
+            let value = sequence.value(step).unwrap_or(0) as i32;
+            let bar_height = (value.abs() * scale + max_abs - 1) / max_abs;
+            let bar_y = if value > 0 {
+                zero_y - bar_height
+            } else {
+                zero_y
+            };
+            set_color(Color::Primary);
+            rect(
+                relative_x(x),
+                relative_y(bar_y),
+                (step_width - 1) as u32,
+                bar_height.max(1) as u32,
+            );
+
+            let marker = if sequence.loop_point() == Some(step as u8) {
+                "L"
+            } else if sequence.release_point() == Some(step as u8) {
+                "R"
+            } else {
+                continue;
+            };
+            text(marker, relative_x(x), relative_y(top + graph_height + 1));
+        }
+    }
+}
+
+// Instrument index under a mouse position
+pub fn instrument_list_index_at(x: i32, y: i32) -> Option<usize> {
+    if !(11..11 + 0x10 * 9).contains(&y) || x < 0 {
+        return None;
+    }
+    Some((x / 80) as usize * 0x10 + ((y - 11) / 9) as usize).filter(|&a| a < MAX_INSTRUMENTS)
+}
+
+pub fn instrument_list_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
+    let relative_x = |a: i32| a + origin_x;
+    let relative_y = |a: i32| a + origin_y;
+
+    set_color(Color::Primary);
+    text("Instruments", relative_x(10), relative_y(1));
+
+    let selected_instrument_index = tracker.selected_instrument_index();
+    for instrument_index in 0..MAX_INSTRUMENTS {
+        let x = (instrument_index / 0x10) as i32 * 80 + 2;
+        let y = 12 + (instrument_index % 0x10) as i32 * 9;
+        let instrument = tracker.instrument(instrument_index);
+
+        let index_color = if tracker.is_instrument_used(instrument_index) {
+            Color::Primary
+        } else {
+            Color::Light
+        };
+        let index_name = hex(instrument_index as u32, 2);
+        if instrument_index == selected_instrument_index {
+            set_color(Color::Primary);
+            rect(relative_x(x - 1), relative_y(y - 1), 8 * 2 + 1, 9);
+            set_color(Color::Background);
+            text(index_name, relative_x(x), relative_y(y));
+        } else {
+            set_color(index_color);
+            text(index_name, relative_x(x), relative_y(y));
+        }
+
+        set_color(Color::Primary);
+        let signal_width = 8;
+        draw_sqr_waveform(
+            duty_cycle_active_width(instrument.duty_cycle(), signal_width),
+            signal_width,
+            6,
+            relative_x(x + 20),
+            relative_y(y),
+        );
+        draw_envelope(instrument, relative_x(x + 32), relative_y(y), 40, 6);
+    }
+}
+
+fn instrument_input_row(input: InstrumentInput) -> i32 {
+    match input {
+        InstrumentInput::Name => 0,
+        InstrumentInput::DutyCycle => 1,
+        InstrumentInput::Attack => 2,
+        InstrumentInput::Decay => 3,
+        InstrumentInput::Sustain => 4,
+        InstrumentInput::Release => 5,
+        InstrumentInput::Volume => 6,
+        InstrumentInput::Peak => 7,
+        InstrumentInput::NoteSweep => 8,
+        InstrumentInput::Glide => 9,
+        InstrumentInput::Finetune => 10,
+        InstrumentInput::Pan => 11,
+        InstrumentInput::DrumKit => 12,
+        InstrumentInput::DrumKey => 13,
+        InstrumentInput::Drum => 14,
+        InstrumentInput::KitFrequency => 15,
+        InstrumentInput::KitSweep => 16,
+        InstrumentInput::KitEnvelope => 17,
+        InstrumentInput::KitVolume => 18,
+        InstrumentInput::VibratoDepth => 19,
+        InstrumentInput::VibratoSpeed => 20,
+        InstrumentInput::VibratoDelay => 21,
+        InstrumentInput::TremoloDepth => 22,
+        InstrumentInput::TremoloSpeed => 23,
+        InstrumentInput::TremoloDelay => 24,
+        InstrumentInput::Preset => 25,
+    }
+}
+
+const VISIBLE_INPUT_ROWS: i32 = 14;
+
+const INSTRUMENT_INPUTS: [InstrumentInput; 26] = [
+    InstrumentInput::Name,
+    InstrumentInput::DutyCycle,
+    InstrumentInput::Attack,
+    InstrumentInput::Decay,
+    InstrumentInput::Sustain,
+    InstrumentInput::Release,
+    InstrumentInput::Volume,
+    InstrumentInput::Peak,
+    InstrumentInput::NoteSweep,
+    InstrumentInput::Glide,
+    InstrumentInput::Finetune,
+    InstrumentInput::Pan,
+    InstrumentInput::DrumKit,
+    InstrumentInput::DrumKey,
+    InstrumentInput::Drum,
+    InstrumentInput::KitFrequency,
+    InstrumentInput::KitSweep,
+    InstrumentInput::KitEnvelope,
+    InstrumentInput::KitVolume,
+    InstrumentInput::VibratoDepth,
+    InstrumentInput::VibratoSpeed,
+    InstrumentInput::VibratoDelay,
+    InstrumentInput::TremoloDepth,
+    InstrumentInput::TremoloSpeed,
+    InstrumentInput::TremoloDelay,
+    InstrumentInput::Preset,
+];
+
+fn instrument_input_scroll(focus: InstrumentInput) -> i32 {
+    // Keep the focused input and the one after it on the screen
+    (instrument_input_row(focus) + 2 - VISIBLE_INPUT_ROWS).max(0)
+}
+
+// Instrument input under a mouse position, given the input that sets the scroll
+pub fn instrument_input_at(focus: InstrumentInput, y: i32) -> Option<InstrumentInput> {
+    if !(19..19 + VISIBLE_INPUT_ROWS * 10).contains(&y) {
+        return None;
+    }
+    let row = (y - 19) / 10 + instrument_input_scroll(focus);
+    INSTRUMENT_INPUTS
+        .into_iter()
+        .find(|&input| instrument_input_row(input) == row)
+}
+
+fn draw_lfo_waveform(instrument: &Instrument, tremolo: bool, x: i32, y: i32) {
+    let width = 18;
+    let frames_per_pixel = 4;
+    let amplitude = 3;
+    let sample = |px: i32| {
+        let frame = (px * frames_per_pixel) as u32;
+        if tremolo {
+            // Tremolo only ever lowers the volume, so full volume is the top line
+            y - (2 * instrument.tremolo_at(frame) as i32 - 100) * amplitude / 100
+        } else {
+            y - instrument.vibrato().wave_at(frame) * amplitude / LFO_PEAK
+        }
+    };
+    for px in 0..width {
+        line(x + px, sample(px), x + px + 1, sample(px + 1));
+    }
+}
+
 pub fn instrument_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
     let relative_x = |a: i32| a + origin_x;
     let relative_y = |a: i32| a + origin_y;
@@ -139,7 +567,7 @@ pub fn instrument_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
 
     let selected_instrument_index = tracker.selected_instrument_index();
     text(
-        format!("Instrument {:02X}", selected_instrument_index),
+        "Instrument ".to_string() + &hex(selected_instrument_index as u32, 2),
         relative_x(10),
         relative_y(10),
     );
@@ -149,111 +577,431 @@ pub fn instrument_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
 
     let value_column_x = 120;
 
+    let scroll = instrument_input_scroll(focus);
+    let row_y = |row: i32| {
+        if (scroll..scroll + VISIBLE_INPUT_ROWS).contains(&row) {
+            Some(20 + (row - scroll) * 10)
+        } else {
+            None
+        }
+    };
+    let input_y = |id: InstrumentInput| row_y(instrument_input_row(id));
+
+    let name_x = 10;
+    if let Some(name_y) = input_y(InstrumentInput::Name) {
+        if focus == InstrumentInput::Name {
+            let cursor_x = name_x + tracker.instrument_name_cursor() as i32 * 8;
+            rect(relative_x(cursor_x - 1), relative_y(name_y - 1), 8 + 1, 9);
+            for (position, c) in instrument.name().bytes().enumerate() {
+                let x = name_x + position as i32 * 8;
+                if x == cursor_x {
+                    set_color(Color::Background);
+                    text_bytes([c], relative_x(x), relative_y(name_y));
+                    set_color(Color::Primary);
+                } else if c == b' ' {
+                    set_color(Color::Light);
+                    text("_", relative_x(x), relative_y(name_y));
+                    set_color(Color::Primary);
+                } else {
+                    text_bytes([c], relative_x(x), relative_y(name_y));
+                }
+            }
+        } else if instrument.has_name() {
+            text(instrument.name(), relative_x(name_x), relative_y(name_y));
+        } else {
+            set_color(Color::Light);
+            text("no name", relative_x(name_x), relative_y(name_y));
+            set_color(Color::Primary);
+        }
+    }
+
     let duty_cycle_x = 10;
-    let duty_cycle_y = 30;
     let text_size_x = value_column_x - 10;
-    text(
-        "Duty cycle",
-        relative_x(duty_cycle_x),
-        relative_y(duty_cycle_y),
-    );
-    if focus == InstrumentInput::DutyCycle {
-        rect(
-            relative_x(duty_cycle_x + text_size_x - 1),
+    if let Some(duty_cycle_y) = input_y(InstrumentInput::DutyCycle) {
+        text(
+            "Duty cycle",
+            relative_x(duty_cycle_x),
+            relative_y(duty_cycle_y),
+        );
+        if focus == InstrumentInput::DutyCycle {
+            rect(
+                relative_x(duty_cycle_x + text_size_x - 1),
+                relative_y(duty_cycle_y - 1),
+                18,
+                10,
+            );
+            set_color(Color::Background);
+        }
+        let signal_width = 16;
+        let signal_active = duty_cycle_active_width(instrument.duty_cycle(), signal_width);
+
+        draw_sqr_waveform(
+            signal_active,
+            signal_width,
+            8,
+            relative_x(duty_cycle_x + text_size_x),
             relative_y(duty_cycle_y - 1),
-            18,
-            10,
         );
-        set_color(Color::Background);
+        if focus == InstrumentInput::DutyCycle {
+            set_color(Color::Primary);
+        }
     }
-    let signal_width = 16;
-    let signal_active = match instrument.duty_cycle() {
-        DutyCycle::Eighth => signal_width / 8,
-        DutyCycle::Fourth => signal_width / 4,
-        DutyCycle::Half => signal_width / 2,
-        DutyCycle::ThreeFourth => 3 * signal_width / 4,
+
+    let text_input = |label: &str, value: &str, id: InstrumentInput| {
+        let y = match input_y(id) {
+            Some(y) => y,
+            None => return,
+        };
+        set_color(Color::Primary);
+        text(label, relative_x(10), relative_y(y));
+        value_text(
+            value.as_bytes(),
+            relative_x(value_column_x),
+            relative_y(y),
+            9,
+            focus == id,
+        );
+    };
+
+    let input =
+        |label: &str, value: u8, id: InstrumentInput| text_input(label, &hex(value.into(), 2), id);
+
+    input("Attack", instrument.attack(), InstrumentInput::Attack);
+    input("Decay", instrument.decay(), InstrumentInput::Decay);
+    input("Sustain", instrument.sustain(), InstrumentInput::Sustain);
+    input("Release", instrument.release(), InstrumentInput::Release);
+    input("Volume", instrument.volume(), InstrumentInput::Volume);
+    input("Peak", instrument.peak(), InstrumentInput::Peak);
+
+    let note_sweep = instrument.note_sweep();
+    let note_sweep = if note_sweep < 0 {
+        "-".to_string() + &hex(note_sweep.unsigned_abs().into(), 2)
+    } else {
+        hex(note_sweep as u32, 2)
+    };
+    text_input("Note sweep", &note_sweep, InstrumentInput::NoteSweep);
+
+    input("Glide", instrument.glide(), InstrumentInput::Glide);
+
+    text_input(
+        "Finetune",
+        &signed_decimal(instrument.finetune().into()),
+        InstrumentInput::Finetune,
+    );
+    text_input("Pan", instrument.pan().to_render(), InstrumentInput::Pan);
+    let drum_kit = if instrument.is_drum_kit() {
+        "ON"
+    } else {
+        "OFF"
     };
+    text_input("Drum kit", drum_kit, InstrumentInput::DrumKit);
 
-    draw_sqr_waveform(
-        signal_active,
-        signal_width,
-        8,
-        relative_x(duty_cycle_x + text_size_x),
-        relative_y(duty_cycle_y - 1),
+    let drum_key = tracker.drum_key();
+    text_input(
+        "Drum key",
+        &note_to_render(NOTE_C3_INDEX + drum_key),
+        InstrumentInput::DrumKey,
     );
-    if focus == InstrumentInput::DutyCycle {
+    text_input(
+        "Drum",
+        instrument.kit_drum(drum_key).name(),
+        InstrumentInput::Drum,
+    );
+    let kit = instrument.kit_params();
+    text_input(
+        "Kit freq",
+        &signed_decimal(kit.frequency().into()),
+        InstrumentInput::KitFrequency,
+    );
+    text_input(
+        "Kit sweep",
+        &signed_decimal(kit.sweep().into()),
+        InstrumentInput::KitSweep,
+    );
+    text_input(
+        "Kit length",
+        &signed_decimal(kit.envelope().into()),
+        InstrumentInput::KitEnvelope,
+    );
+    input("Kit volume", kit.volume(), InstrumentInput::KitVolume);
+
+    input(
+        "Vib depth",
+        instrument.vibrato_depth(),
+        InstrumentInput::VibratoDepth,
+    );
+    input(
+        "Vib speed",
+        instrument.vibrato_speed(),
+        InstrumentInput::VibratoSpeed,
+    );
+    input(
+        "Vib delay",
+        instrument.vibrato_delay(),
+        InstrumentInput::VibratoDelay,
+    );
+    if let Some(y) = input_y(InstrumentInput::VibratoSpeed) {
         set_color(Color::Primary);
+        draw_lfo_waveform(instrument, false, relative_x(140), relative_y(y + 3));
     }
 
-    let input = |x: i32, y: i32, label: &str, value: u8, id: InstrumentInput| {
+    input(
+        "Trem depth",
+        instrument.tremolo_depth(),
+        InstrumentInput::TremoloDepth,
+    );
+    input(
+        "Trem speed",
+        instrument.tremolo_speed(),
+        InstrumentInput::TremoloSpeed,
+    );
+    input(
+        "Trem delay",
+        instrument.tremolo_delay(),
+        InstrumentInput::TremoloDelay,
+    );
+    if let Some(y) = input_y(InstrumentInput::TremoloSpeed) {
         set_color(Color::Primary);
-        text(label, relative_x(x), relative_y(y));
-        let value_x: i32 = value_column_x;
-        if focus == id {
-            let rect_width: u32 = 8 * 2 + 1;
-            rect(relative_x(value_x - 1), relative_y(y - 1), rect_width, 9);
-            set_color(Color::Background);
-        }
-        text(format!("{:02X}", value), relative_x(value_x), relative_y(y));
-        if focus == id {
-            set_color(Color::Primary);
-        }
-    };
+        draw_lfo_waveform(instrument, true, relative_x(140), relative_y(y + 3));
+    }
 
-    let input_i8 = |x: i32, y: i32, label: &str, value: i8, id: InstrumentInput| {
+    let preset_x = 10;
+    let preset_value_x = value_column_x - 8 * 2;
+    if let Some(preset_y) = input_y(InstrumentInput::Preset) {
         set_color(Color::Primary);
-        text(label, relative_x(x), relative_y(y));
-        let value_x: i32 = value_column_x;
-        if focus == id {
-            let characters_count = if value < 0 { 3 } else { 2 };
-            let rect_width: u32 = 8 * characters_count + 1;
-            rect(relative_x(value_x - 1), relative_y(y - 1), rect_width, 9);
+        text("Preset", relative_x(preset_x), relative_y(preset_y));
+        if focus == InstrumentInput::Preset {
+            let rect_width: u32 = 8 * INSTRUMENT_NAME_LENGTH as u32 + 1;
+            rect(
+                relative_x(preset_value_x - 1),
+                relative_y(preset_y - 1),
+                rect_width,
+                9,
+            );
             set_color(Color::Background);
         }
-        let format_string = if value < 0 { format!("-{:02X}", -value) } else { format!( "{:02X}", value) };
-        text(format_string, relative_x(value_x), relative_y(y));
-        if focus == id {
-            set_color(Color::Primary);
+        text(
+            tracker.selected_preset().name(),
+            relative_x(preset_value_x),
+            relative_y(preset_y),
+        );
+    }
+    if let Some(hint_y) = row_y(instrument_input_row(InstrumentInput::Preset) + 1) {
+        set_color(Color::Light);
+        text_bytes(
+            b"load:   \x80+\x86",
+            relative_x(preset_x),
+            relative_y(hint_y),
+        );
+    }
+    set_color(Color::Primary);
+}
+
+fn song_settings_input_scroll(focus: SongSettingsInput) -> i32 {
+    (song_settings_input_row(focus) + 2 - VISIBLE_INPUT_ROWS).max(0)
+}
+
+// Song settings input under a mouse position, given the input that sets the scroll
+pub fn song_settings_input_at(focus: SongSettingsInput, y: i32) -> Option<SongSettingsInput> {
+    if !(19..19 + VISIBLE_INPUT_ROWS * 10).contains(&y) {
+        return None;
+    }
+    let row = (y - 19) / 10 + song_settings_input_scroll(focus);
+    let input = (0..row).fold(SongSettingsInput::A4, |a, _| a.next());
+    Some(input).filter(|&a| song_settings_input_row(a) == row)
+}
+
+fn song_settings_input_row(input: SongSettingsInput) -> i32 {
+    match input {
+        SongSettingsInput::A4 => 0,
+        SongSettingsInput::Temperament => 1,
+        SongSettingsInput::Key => 2,
+        SongSettingsInput::Scale => 3,
+        SongSettingsInput::EchoSource => 4,
+        SongSettingsInput::EchoTarget => 5,
+        SongSettingsInput::EchoDelay => 6,
+        SongSettingsInput::EchoVolume => 7,
+        SongSettingsInput::CustomCents(pitch_class) => 8 + pitch_class as i32,
+    }
+}
+
+pub fn song_settings_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
+    let relative_x = |a: i32| a + origin_x;
+    let relative_y = |a: i32| a + origin_y;
+
+    set_color(Color::Primary);
+    text("Song settings", relative_x(10), relative_y(10));
+
+    let tuning = tracker.tuning();
+    let focus = tracker.song_settings_focus();
+    let value_column_x = 112;
+
+    let scroll = song_settings_input_scroll(focus);
+    let input = |label: &str, value: String, id: SongSettingsInput| {
+        let row = song_settings_input_row(id);
+        if !(scroll..scroll + VISIBLE_INPUT_ROWS).contains(&row) {
+            return;
         }
+        let y = 20 + (row - scroll) * 10;
+        set_color(Color::Primary);
+        text(label, relative_x(10), relative_y(y));
+        value_text(
+            value.as_bytes(),
+            relative_x(value_column_x),
+            relative_y(y),
+            9,
+            focus == id,
+        );
     };
 
     input(
-        10,
-        40,
-        "Attack",
-        instrument.attack(),
-        InstrumentInput::Attack,
+        "A4 Hz",
+        decimal((tuning.a4() / 10).into(), 1) + "." + &decimal((tuning.a4() % 10).into(), 1),
+        SongSettingsInput::A4,
     );
-
-    input(10, 50, "Decay", instrument.decay(), InstrumentInput::Decay);
-
     input(
-        10,
-        60,
-        "Sustain",
-        instrument.sustain(),
-        InstrumentInput::Sustain,
+        "Tuning",
+        tuning.temperament().to_render().to_string(),
+        SongSettingsInput::Temperament,
+    );
+    input(
+        "Key",
+        pitch_class_to_render(tracker.key() as usize).to_string(),
+        SongSettingsInput::Key,
+    );
+    input(
+        "Scale",
+        tracker.scale().to_render().to_string(),
+        SongSettingsInput::Scale,
+    );
+    let echo = tracker.echo();
+    input(
+        "Echo from",
+        echo.source().map_or("OFF", |a| a.to_render()).to_string(),
+        SongSettingsInput::EchoSource,
+    );
+    input(
+        "Echo to",
+        echo.target().to_render().to_string(),
+        SongSettingsInput::EchoTarget,
     );
-
     input(
-        10,
-        70,
-        "Release",
-        instrument.release(),
-        InstrumentInput::Release,
+        "Echo ticks",
+        hex(echo.delay().into(), 1),
+        SongSettingsInput::EchoDelay,
     );
+    input(
+        "Echo vol",
+        decimal(echo.volume().into(), 1) + "%",
+        SongSettingsInput::EchoVolume,
+    );
+    for pitch_class in 0..NOTES_PER_OCTAVE as usize {
+        input(
+            &("Custom ".to_string() + pitch_class_to_render(pitch_class)),
+            signed_decimal(tuning.custom_cents(pitch_class).into()),
+            SongSettingsInput::CustomCents(pitch_class),
+        );
+    }
+}
+
+// Controls input under a mouse position
+pub fn controls_input_at(y: i32) -> Option<ControlsInput> {
+    if y < 19 {
+        return None;
+    }
+    let row = ((y - 19) / 10) as usize;
+    match Action::ALL.get(row) {
+        Some(&action) => Some(ControlsInput::Binding(action)),
+        None if row == Action::ALL.len() => Some(ControlsInput::Preview),
+        None => None,
+    }
+}
+
+pub fn controls_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
+    let relative_x = |a: i32| a + origin_x;
+    let relative_y = |a: i32| a + origin_y;
+
+    set_color(Color::Primary);
+    text("Controls", relative_x(10), relative_y(10));
+
+    let focus = tracker.controls_focus();
+    let value_column_x = 128;
+
+    let input = |label: &str, value: &[u8], id: ControlsInput, row: usize| {
+        let y = 20 + row as i32 * 10;
+        set_color(Color::Primary);
+        text(label, relative_x(10), relative_y(y));
+        if focus == id {
+            let rect_width: u32 = 8 * value.len() as u32 + 1;
+            rect(
+                relative_x(value_column_x - 1),
+                relative_y(y - 1),
+                rect_width,
+                9,
+            );
+            set_color(Color::Background);
+        }
+        text_bytes(value, relative_x(value_column_x), relative_y(y));
+    };
 
+    for (row, action) in Action::ALL.into_iter().enumerate() {
+        input(
+            action.to_render(),
+            &tracker.bindings().event(action).to_render(),
+            ControlsInput::Binding(action),
+            row,
+        );
+    }
+    let preview: &[u8] = if tracker.is_preview_enabled() {
+        b"ON"
+    } else {
+        b"OFF"
+    };
     input(
-        10,
-        80,
-        "Volume",
-        instrument.volume(),
-        InstrumentInput::Volume,
+        "Preview",
+        preview,
+        ControlsInput::Preview,
+        Action::ALL.len(),
     );
 
-    input(10, 90, "Peak", instrument.peak(), InstrumentInput::Peak);
+    set_color(Color::Light);
+    let hint_y = 40 + Action::ALL.len() as i32 * 10;
+    text_bytes(b"change: \x80+\x84\x85", relative_x(10), relative_y(hint_y));
+    text("save to keep", relative_x(10), relative_y(hint_y + 10));
+    set_color(Color::Primary);
+}
 
-    input_i8(10, 100, "Note sweep", instrument.note_sweep(), InstrumentInput::NoteSweep);
+// Left edge of the channel's column on the song screen
+impl Channel {
+    fn to_x(self) -> i32 {
+        let x0 = 24;
+        let d = 34;
+        match self {
+            Channel::Pulse1 => x0,
+            Channel::Pulse2 => x0 + d,
+            Channel::Triangle => x0 + d * 2,
+            Channel::Noise => x0 + d * 3,
+        }
+    }
+}
+
+// Song row, channel and column under a mouse position
+pub fn song_cell_at(tracker: &Tracker, x: i32, y: i32) -> Option<(usize, Channel, SongColumn)> {
+    if y < 29 {
+        return None;
+    }
+    let row = ((y - 29) / 10) as usize;
+    if row >= tracker.song().len() {
+        return None;
+    }
+    Channel::iterator().find_map(|channel| {
+        let channel_x = channel.to_x();
+        match x - channel_x {
+            -1..=14 => Some((row, channel, SongColumn::Pattern)),
+            15..=32 => Some((row, channel, SongColumn::Transpose)),
+            _ => None,
+        }
+    })
 }
 
 pub fn song_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
@@ -262,16 +1010,25 @@ pub fn song_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
 
     set_color(Color::Primary);
 
-    impl Channel {
-        fn to_x(&self) -> i32 {
-            let x0 = 30;
-            let d = 30;
-            match self {
-                Channel::Pulse1 => x0,
-                Channel::Pulse2 => x0 + d,
-                Channel::Triangle => x0 + d * 2,
-                Channel::Noise => x0 + d * 3,
-            }
+    let echo = tracker.echo();
+    match echo.source() {
+        Some(source) => text(
+            "Echo ".to_string()
+                + source.to_render()
+                + ">"
+                + echo.target().to_render()
+                + " "
+                + &hex(echo.delay().into(), 1)
+                + "t "
+                + &decimal(echo.volume().into(), 1)
+                + "%",
+            relative_x(10),
+            relative_y(0),
+        ),
+        None => {
+            set_color(Color::Light);
+            text("Echo off", relative_x(10), relative_y(0));
+            set_color(Color::Primary);
         }
     }
 
@@ -281,18 +1038,20 @@ pub fn song_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
     text("NS", relative_x(Channel::Noise.to_x()), relative_y(10));
 
     let selected_channel = tracker.selected_channel();
+    let song_column = tracker.song_column();
     let row = tracker.song_cursor_row();
     let song = tracker.song();
     for channel in Channel::iterator() {
         let x = channel.to_x();
-        for line in 0..song.len() {
+        for (line, song_row) in song.iter().enumerate() {
             let y: i32 = 30 + line as i32 * 10;
 
-            let val = match song[line].channel(&channel) {
-                Some(index) => format!("{:02X}", index),
+            let val = match song_row.channel(&channel) {
+                Some(index) => hex(*index as u32, 2),
                 None => "--".to_string(),
             };
-            if *selected_channel == channel && line == row {
+            let selected = selected_channel == channel && line == row;
+            if selected && song_column == SongColumn::Pattern {
                 set_color(Color::Primary);
                 rect(relative_x(x - 1), relative_y(y - 1), 18, 9);
                 set_color(Color::Background);
@@ -302,6 +1061,22 @@ pub fn song_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
                 text(val, relative_x(x), relative_y(y));
             }
 
+            let transpose = song[line].transpose(&channel);
+            let transpose_name = if transpose < 0 {
+                "-".to_string() + &hex(transpose.unsigned_abs().into(), 1)
+            } else {
+                "+".to_string() + &hex(transpose as u32, 1)
+            };
+            let transpose_x = x + 8 * 2;
+            if selected && song_column == SongColumn::Transpose {
+                rect(relative_x(transpose_x - 1), relative_y(y - 1), 18, 9);
+                set_color(Color::Background);
+                text(transpose_name, relative_x(transpose_x), relative_y(y));
+                set_color(Color::Primary);
+            } else if transpose != 0 {
+                text(transpose_name, relative_x(transpose_x), relative_y(y));
+            }
+
             if let PlayMode::Song = tracker.play_mode() {
                 if tracker.song_tick() == line {
                     text(">", relative_x(x - 10), relative_y(y));
@@ -309,19 +1084,115 @@ pub fn song_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
             }
         }
     }
+
+    for (player, state) in tracker.players().iter().enumerate().skip(1) {
+        if state.is_active() {
+            let x = state.channel().to_x();
+            let y: i32 = 30 + row as i32 * 10;
+            player_outline(player, relative_x(x - 2), relative_y(y - 2), 20, 11);
+        }
+    }
+    set_color(Color::Primary);
+
+    if let Some(position) = tracker.play_position() {
+        text(
+            duration_to_render(position),
+            relative_x(10),
+            relative_y(140),
+        );
+    }
 }
 
-pub fn not_implemented_screen() {
+fn draw_pattern_preview(tracker: &Tracker, pattern_index: usize, x: i32, y: i32) {
+    let step_width: u32 = 3;
+    let height: u32 = 7;
+    let max_note_index = NOTE_FREQ.len() as u32 - 1;
+
+    set_color(Color::Light);
+    hline(x, y + height as i32, step_width * 16);
+
+    set_color(Color::Primary);
+    for (step, note) in tracker.pattern(pattern_index).iter().enumerate() {
+        if let Some(note) = note.filter(|a| a.is_pitched()) {
+            let note_height = 1 + note.note_index() as u32 * (height - 1) / max_note_index;
+            rect(
+                x + step as i32 * step_width as i32,
+                y + (height - note_height) as i32,
+                step_width - 1,
+                note_height,
+            );
+        }
+    }
+}
+
+// Pattern index under a mouse position
+pub fn pattern_list_index_at(y: i32) -> Option<usize> {
+    if !(11..11 + MAX_PATTERNS as i32 * 9).contains(&y) {
+        return None;
+    }
+    Some(((y - 11) / 9) as usize)
+}
+
+pub fn pattern_list_screen(tracker: &Tracker, origin_x: i32, origin_y: i32) {
+    let relative_x = |a: i32| a + origin_x;
+    let relative_y = |a: i32| a + origin_y;
+
     set_color(Color::Primary);
-    text("Screen is not\nimplemented", 10, 10);
+    text("Patterns", relative_x(10), relative_y(1));
+
+    let selected_pattern = tracker.selected_pattern();
+    for pattern_index in 0..MAX_PATTERNS {
+        let y = 12 + pattern_index as i32 * 9;
+
+        let index_name = hex(pattern_index as u32, 2);
+        if pattern_index == selected_pattern {
+            rect(relative_x(9), relative_y(y - 1), 8 * 2 + 1, 9);
+            set_color(Color::Background);
+            text(index_name, relative_x(10), relative_y(y));
+            set_color(Color::Primary);
+        } else {
+            text(index_name, relative_x(10), relative_y(y));
+        }
+
+        draw_pattern_preview(tracker, pattern_index, relative_x(34), relative_y(y));
+
+        let usage_count = tracker.pattern_usage_count(pattern_index);
+        if usage_count > 0 {
+            text(
+                "x".to_string() + &decimal(usage_count as u32, 1),
+                relative_x(90),
+                relative_y(y),
+            );
+        }
+
+        if tracker.is_pattern_empty(pattern_index) {
+            set_color(Color::Light);
+            text("empty", relative_x(114), relative_y(y));
+            set_color(Color::Primary);
+        }
+    }
+}
+
+// Clicking the header of a screen switches screens
+pub fn is_header(screen: Screen, x: i32, y: i32) -> bool {
+    match screen {
+        // The first step shares the top row with the pattern title
+        Screen::Pattern => y < 10 && x >= 104,
+        Screen::Song | Screen::Instrument | Screen::SongSettings | Screen::Controls => y < 20,
+        _ => y < 10,
+    }
 }
 
 fn render_screen(screen: &Screen, tracker: &Tracker, x: i32, y: i32) {
     match screen {
         Screen::Pattern => pattern_screen(tracker, x, y),
         Screen::Instrument => instrument_screen(tracker, x, y),
+        Screen::Macro => macro_screen(tracker, x, y),
+        Screen::InstrumentList => instrument_list_screen(tracker, x, y),
         Screen::Song => song_screen(tracker, x, y),
-        _ => not_implemented_screen(),
+        Screen::PatternList => pattern_list_screen(tracker, x, y),
+        Screen::SongSettings => song_settings_screen(tracker, x, y),
+        Screen::Controls => controls_screen(tracker, x, y),
     }
 }
 
@@ -336,6 +1207,17 @@ fn direction(from: Screen, to: Screen) -> TransitionDirection {
         (Screen::Pattern, Screen::Song) => TransitionDirection::Left,
         (Screen::Pattern, Screen::Instrument) => TransitionDirection::Right,
         (Screen::Instrument, Screen::Pattern) => TransitionDirection::Left,
+        (Screen::Instrument, Screen::Macro) => TransitionDirection::Right,
+        (Screen::Macro, Screen::Instrument) => TransitionDirection::Left,
+        (Screen::Macro, Screen::InstrumentList) => TransitionDirection::Right,
+        (Screen::InstrumentList, Screen::Macro) => TransitionDirection::Left,
+        (Screen::InstrumentList, Screen::Controls) => TransitionDirection::Right,
+        (Screen::Controls, Screen::InstrumentList) => TransitionDirection::Left,
+        (Screen::PatternList, Screen::Song) => TransitionDirection::Right,
+        (Screen::Song, Screen::PatternList) => TransitionDirection::Left,
+        (Screen::PatternList, Screen::Pattern) => TransitionDirection::Right,
+        (Screen::SongSettings, Screen::PatternList) => TransitionDirection::Right,
+        (Screen::PatternList, Screen::SongSettings) => TransitionDirection::Left,
         _ => TransitionDirection::Right,
     }
 }
@@ -363,4 +1245,10 @@ pub fn render_screens(screens: &Screens, tracker: &Tracker) {
             render_screen(to, tracker, x_to, 0);
         }
     }
+    if let Some(notice) = tracker.notice() {
+        set_color(Color::Background);
+        rect(0, SCREEN_SIZE as i32 - 11, SCREEN_SIZE, 11);
+        set_color(Color::Primary);
+        text_bytes(notice, 1, SCREEN_SIZE as i32 - 9);
+    }
 }
@@ -0,0 +1,154 @@
+use crate::notes::{NOTES_PER_OCTAVE, NOTE_FREQ};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scale {
+    Chromatic,
+    Major,
+    Minor,
+    Dorian,
+    Mixolydian,
+    MajorPentatonic,
+    MinorPentatonic,
+    Blues,
+}
+
+impl Scale {
+    pub fn next(&self) -> Self {
+        match self {
+            Scale::Chromatic => Scale::Major,
+            Scale::Major => Scale::Minor,
+            Scale::Minor => Scale::Dorian,
+            Scale::Dorian => Scale::Mixolydian,
+            Scale::Mixolydian => Scale::MajorPentatonic,
+            Scale::MajorPentatonic => Scale::MinorPentatonic,
+            Scale::MinorPentatonic => Scale::Blues,
+            &blues @ Scale::Blues => blues,
+        }
+    }
+
+    pub fn prev(&self) -> Self {
+        match self {
+            &chromatic @ Scale::Chromatic => chromatic,
+            Scale::Major => Scale::Chromatic,
+            Scale::Minor => Scale::Major,
+            Scale::Dorian => Scale::Minor,
+            Scale::Mixolydian => Scale::Dorian,
+            Scale::MajorPentatonic => Scale::Mixolydian,
+            Scale::MinorPentatonic => Scale::MajorPentatonic,
+            Scale::Blues => Scale::MinorPentatonic,
+        }
+    }
+
+    pub fn to_render(self) -> &'static str {
+        match self {
+            Scale::Chromatic => "CHROMA",
+            Scale::Major => "MAJOR",
+            Scale::Minor => "MINOR",
+            Scale::Dorian => "DORIAN",
+            Scale::Mixolydian => "MIXOLY",
+            Scale::MajorPentatonic => "MAJPEN",
+            Scale::MinorPentatonic => "MINPEN",
+            Scale::Blues => "BLUES",
+        }
+    }
+
+    pub fn to_bits(self) -> u8 {
+        match self {
+            Scale::Chromatic => 0,
+            Scale::Major => 1,
+            Scale::Minor => 2,
+            Scale::Dorian => 3,
+            Scale::Mixolydian => 4,
+            Scale::MajorPentatonic => 5,
+            Scale::MinorPentatonic => 6,
+            Scale::Blues => 7,
+        }
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        match bits {
+            1 => Scale::Major,
+            2 => Scale::Minor,
+            3 => Scale::Dorian,
+            4 => Scale::Mixolydian,
+            5 => Scale::MajorPentatonic,
+            6 => Scale::MinorPentatonic,
+            7 => Scale::Blues,
+            _ => Scale::Chromatic,
+        }
+    }
+
+    // Semitones above the key
+    fn intervals(self) -> &'static [u8] {
+        match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Blues => &[0, 3, 5, 6, 7, 10],
+        }
+    }
+
+    // `key` is a pitch class, 0 is C
+    pub fn contains(self, key: u8, note_index: usize) -> bool {
+        let semitones =
+            (note_index + NOTES_PER_OCTAVE as usize - key as usize) % NOTES_PER_OCTAVE as usize;
+        self.intervals().contains(&(semitones as u8))
+    }
+
+    // First pitch of the scale above `note_index`, the lowest one for None
+    pub fn next_note(self, key: u8, note_index: Option<usize>) -> Option<usize> {
+        let start = note_index.map_or(0, |a| a + 1);
+        (start..NOTE_FREQ.len()).find(|&a| self.contains(key, a))
+    }
+
+    pub fn prev_note(self, key: u8, note_index: usize) -> Option<usize> {
+        (0..note_index).rev().find(|&a| self.contains(key, a))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const C: u8 = 0;
+    const D: u8 = 2;
+
+    #[test]
+    fn steps_through_the_scale_of_the_key() {
+        assert_eq!(Scale::Major.next_note(C, Some(0)), Some(2));
+        assert_eq!(Scale::Major.next_note(C, Some(4)), Some(5));
+        assert_eq!(Scale::Major.next_note(C, Some(11)), Some(12));
+        assert_eq!(Scale::Major.next_note(D, Some(4)), Some(6));
+        assert_eq!(Scale::Major.prev_note(C, 5), Some(4));
+        assert_eq!(Scale::MinorPentatonic.prev_note(C, 12), Some(10));
+    }
+
+    #[test]
+    fn snaps_a_note_outside_the_scale_to_the_next_pitch() {
+        assert!(!Scale::Major.contains(C, 1));
+        assert_eq!(Scale::Major.next_note(C, Some(1)), Some(2));
+        assert_eq!(Scale::Major.prev_note(C, 1), Some(0));
+    }
+
+    #[test]
+    fn starts_from_the_lowest_pitch() {
+        assert_eq!(Scale::Major.next_note(C, None), Some(0));
+        assert_eq!(Scale::Major.next_note(D, None), Some(1));
+    }
+
+    #[test]
+    fn stops_at_the_ends_of_the_range() {
+        let last = NOTE_FREQ.len() - 1;
+        assert_eq!(Scale::Chromatic.next_note(C, Some(last)), None);
+        assert_eq!(Scale::Chromatic.prev_note(C, 0), None);
+    }
+
+    #[test]
+    fn chromatic_contains_every_pitch() {
+        assert!((0..NOTES_PER_OCTAVE as usize).all(|a| Scale::Chromatic.contains(D, a)));
+    }
+}
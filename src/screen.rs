@@ -1,12 +1,54 @@
 #[derive(PartialEq, Clone, Copy)]
 pub enum Screen {
     Song,
-    Chain,
+    PatternList,
     Pattern,
     Instrument,
+    Macro,
+    InstrumentList,
+    SongSettings,
+    Controls,
+}
+
+impl Screen {
+    // Screens in the order the prev and next screen actions walk through them
+    pub fn next(&self) -> Self {
+        match self {
+            Screen::SongSettings => Screen::PatternList,
+            Screen::PatternList => Screen::Song,
+            Screen::Song => Screen::Pattern,
+            Screen::Pattern => Screen::Instrument,
+            Screen::Instrument => Screen::Macro,
+            Screen::Macro => Screen::InstrumentList,
+            Screen::InstrumentList => Screen::Controls,
+            Screen::Controls => Screen::Controls,
+        }
+    }
+
+    pub fn prev(&self) -> Self {
+        match self {
+            Screen::SongSettings => Screen::SongSettings,
+            Screen::PatternList => Screen::SongSettings,
+            Screen::Song => Screen::PatternList,
+            Screen::Pattern => Screen::Song,
+            Screen::Instrument => Screen::Pattern,
+            Screen::Macro => Screen::Instrument,
+            Screen::InstrumentList => Screen::Macro,
+            Screen::Controls => Screen::InstrumentList,
+        }
+    }
 }
 
 pub enum Screens {
     Single(Screen),
     Transition(Screen, Screen, f32),
 }
+
+impl Screens {
+    // The screen that has the handlers, also while sliding in
+    pub fn current(&self) -> Screen {
+        match self {
+            Screens::Single(screen) | Screens::Transition(_, screen, _) => *screen,
+        }
+    }
+}
@@ -1,134 +1,200 @@
-use std::time::Duration;
-
 use crate::{
-    inputs::{InputEvent, Inputs},
-    navigation::go_to_pattern_screen,
+    controls::Action,
+    inputs::{
+        mouse,
+        Button::{Button1, Down, Left, Right, Up},
+        InputEvent, Inputs,
+    },
+    navigation::{add_header_handlers, go_to_pattern_list_screen, go_to_pattern_screen},
+    render::song_cell_at,
     screen::Screen,
-    timers::{ActionId, TIMERS},
-    tracker::{PlayMode, TRACKER},
+    tracker::{tracker, PlayMode, SongColumn},
 };
 
-fn on_button_down_press(inputs: &Inputs) {
+fn on_button_down_press(_inputs: &Inputs) {
+    unsafe { tracker().next_row_song_cursor() }
+}
+
+fn on_button_up_press(_inputs: &Inputs) {
+    unsafe { tracker().prev_row_song_cursor() }
+}
+
+fn on_button_left_press(_inputs: &Inputs) {
+    unsafe { tracker().prev_song_column() }
+}
+
+fn on_button_right_press(_inputs: &Inputs) {
+    unsafe { tracker().next_song_column() }
+}
+
+fn on_add_note(_inputs: &Inputs) {
     unsafe {
-        if inputs.is_button2_pressed() {
-            TIMERS.run_action_debounced(ActionId::Play, Duration::from_millis(200), || {
-                TRACKER.toggle_play(PlayMode::Song)
-            })
-        } else if inputs.is_button1_pressed() {
-        } else {
-            TIMERS.run_action_debounced(ActionId::SongNextRow, Duration::from_millis(200), || {
-                TRACKER.next_row_song_cursor()
-            })
+        let selected_channel = tracker().selected_channel();
+        let selected_row = tracker().song_cursor_row();
+        let song = tracker().song_mut();
+        let row = song.get_mut(selected_row);
+        if let Some(row) = row {
+            if row.channel(&selected_channel).is_none() {
+                row.set_channel_value(&selected_channel, Some(0));
+            }
         }
     }
 }
 
-fn on_button_up_press(inputs: &Inputs) {
+const CLEAR_UNUSED_NOTICE: &[u8] = b"Clear unused? \x80+\x87";
+
+// Clearing can't be undone, so it takes a second press while asked
+fn on_button_1_down_press(inputs: &Inputs) {
     unsafe {
-        if inputs.is_button1_pressed() {
-        } else if inputs.is_button2_pressed() {
-            TIMERS.run_action_debounced(ActionId::Persist, Duration::from_millis(1000), || {
-                TRACKER.persist();
-            })
+        if inputs.is_repeat() {
+            return;
+        }
+        if tracker().notice() == Some(CLEAR_UNUSED_NOTICE) {
+            tracker().clear_unused_patterns();
+            tracker().notify(b"Unused cleared");
         } else {
-            TIMERS.run_action_debounced(ActionId::SongPrevRow, Duration::from_millis(200), || {
-                TRACKER.prev_row_song_cursor()
-            })
+            tracker().notify(CLEAR_UNUSED_NOTICE);
         }
     }
 }
 
-fn on_button_left_press(inputs: &Inputs) {
+fn on_button_1_up_press(inputs: &Inputs) {
     unsafe {
-        if inputs.is_button2_pressed() {
-        } else if inputs.is_button1_pressed() {
-            TIMERS.run_action_debounced(
-                ActionId::SongDecrementPattern,
-                Duration::from_millis(200),
-                || {
-                    let selected_row = TRACKER.song_cursor_row();
-                    let song = TRACKER.song_mut();
-                    let row = song.get_mut(selected_row);
-                    if let Some(row) = row {
-                        let selected_channel = TRACKER.selected_channel();
-                        row.decrement_channel_value(selected_channel)
-                    }
-                },
-            )
-        } else {
-            TIMERS.run_action_debounced(
-                ActionId::SongPrevChannel,
-                Duration::from_millis(200),
-                || TRACKER.prev_channel(),
-            )
+        if !inputs.is_repeat() {
+            let selected_channel = tracker().selected_channel();
+            let selected_row = tracker().song_cursor_row();
+            let pattern_index = match tracker().song().get(selected_row) {
+                Some(row) => *row.channel(&selected_channel),
+                None => None,
+            };
+            if let Some(clone_index) = pattern_index.and_then(|i| tracker().clone_pattern(i)) {
+                if let Some(row) = tracker().song_mut().get_mut(selected_row) {
+                    row.set_channel_value(&selected_channel, Some(clone_index));
+                }
+            }
         }
     }
 }
 
-fn on_button_right_press(inputs: &Inputs) {
+fn on_button_1_left_press(_inputs: &Inputs) {
     unsafe {
-        if inputs.is_button2_pressed() {
-            TIMERS.run_action_debounced(
-                ActionId::NavNextScreen,
-                Duration::from_millis(200),
-                || {
-                    let selected_row = TRACKER.song_cursor_row();
-                    let song = TRACKER.song();
-                    let row = song.get(selected_row);
-                    let selected_channel = TRACKER.selected_channel();
-                    let selected_pattern = match row {
-                        Some(r) => r.channel(selected_channel).unwrap_or(0),
-                        None => 0,
-                    };
-                    TRACKER.set_selected_pattern(selected_pattern);
-                    go_to_pattern_screen(Screen::Song);
-                },
-            );
-        } else if inputs.is_button1_pressed() {
-            TIMERS.run_action_debounced(
-                ActionId::SongIncrementPattern,
-                Duration::from_millis(200),
-                || {
-                    let selected_row = TRACKER.song_cursor_row();
-                    let song = TRACKER.song_mut();
-                    let row = song.get_mut(selected_row);
-                    if let Some(row) = row {
-                        let selected_channel = TRACKER.selected_channel();
-                        row.increment_channel_value(selected_channel)
-                    }
-                },
-            )
-        } else {
-            TIMERS.run_action_debounced(
-                ActionId::SongNextChannel,
-                Duration::from_millis(200),
-                || TRACKER.next_channel(),
-            )
+        let selected_row = tracker().song_cursor_row();
+        let selected_channel = tracker().selected_channel();
+        let song_column = tracker().song_column();
+        if let Some(row) = tracker().song_mut().get_mut(selected_row) {
+            match song_column {
+                SongColumn::Pattern => row.decrement_channel_value(&selected_channel),
+                SongColumn::Transpose => row.update_transpose(&selected_channel, |a| a - 1),
+            }
         }
     }
 }
 
-fn on_button_1_press(_inputs: &Inputs) {
+fn on_button_1_right_press(_inputs: &Inputs) {
     unsafe {
-        TIMERS.run_action_debounced(ActionId::SongAddPattern, Duration::from_millis(200), || {
-            let selected_channel = TRACKER.selected_channel();
-            let selected_row = TRACKER.song_cursor_row();
-            let song = TRACKER.song_mut();
-            let row = song.get_mut(selected_row);
-            if let Some(row) = row {
-                if let None = row.channel(selected_channel) {
-                    row.set_channel_value(selected_channel, Some(0));
-                }
+        let selected_row = tracker().song_cursor_row();
+        let selected_channel = tracker().selected_channel();
+        let song_column = tracker().song_column();
+        if let Some(row) = tracker().song_mut().get_mut(selected_row) {
+            match song_column {
+                SongColumn::Pattern => row.increment_channel_value(&selected_channel),
+                SongColumn::Transpose => row.update_transpose(&selected_channel, |a| a + 1),
             }
-        });
+        }
+    }
+}
+
+fn on_play(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().toggle_play(PlayMode::Song)
+        }
+    }
+}
+
+fn on_save(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().persist();
+        }
+    }
+}
+
+fn on_prev_screen(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            go_to_pattern_list_screen(Screen::Song);
+        }
+    }
+}
+
+fn on_next_screen(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            let selected_row = tracker().song_cursor_row();
+            let song = tracker().song();
+            let row = song.get(selected_row);
+            let selected_channel = tracker().selected_channel();
+            let selected_pattern = match row {
+                Some(r) => r.channel(&selected_channel).unwrap_or(0),
+                None => 0,
+            };
+            tracker().set_selected_pattern(selected_pattern);
+            go_to_pattern_screen(Screen::Song);
+        }
+    }
+}
+
+fn on_mouse_click(_inputs: &Inputs) {
+    let (x, y) = mouse().position();
+    unsafe {
+        if let Some((row, channel, column)) = song_cell_at(tracker(), x, y) {
+            tracker().set_song_cursor(row, channel, column);
+        }
+    }
+}
+
+fn on_mouse_right_click(_inputs: &Inputs) {
+    let (x, y) = mouse().position();
+    unsafe {
+        if let Some((row, channel, column)) = song_cell_at(tracker(), x, y) {
+            tracker().set_song_cursor(row, channel, column);
+            if let Some(row) = tracker().song_mut().get_mut(row) {
+                row.set_channel_value(&channel, None);
+            }
+        }
+    }
+}
+
+fn on_mouse_wheel(_inputs: &Inputs) {
+    unsafe {
+        for _ in 0..mouse().wheel_steps() {
+            tracker().next_row_song_cursor()
+        }
+        for _ in mouse().wheel_steps()..0 {
+            tracker().prev_row_song_cursor()
+        }
     }
 }
 
 pub fn add_song_screen_handlers(inputs: &mut Inputs) {
+    let bindings = unsafe { *tracker().bindings() };
     inputs
-        .listen(InputEvent::ButtonDownPress, on_button_down_press)
-        .listen(InputEvent::ButtonUpPress, on_button_up_press)
-        .listen(InputEvent::ButtonLeftPress, on_button_left_press)
-        .listen(InputEvent::ButtonRightPress, on_button_right_press)
-        .listen(InputEvent::Button1Press, on_button_1_press);
+        .listen(InputEvent::Press(Down), on_button_down_press)
+        .listen(InputEvent::Press(Up), on_button_up_press)
+        .listen(InputEvent::Press(Left), on_button_left_press)
+        .listen(InputEvent::Press(Right), on_button_right_press)
+        .listen(bindings.event(Action::AddNote), on_add_note)
+        .listen(InputEvent::Combo(Button1, Down), on_button_1_down_press)
+        .listen(InputEvent::Combo(Button1, Up), on_button_1_up_press)
+        .listen(InputEvent::Combo(Button1, Left), on_button_1_left_press)
+        .listen(InputEvent::Combo(Button1, Right), on_button_1_right_press)
+        .listen(bindings.event(Action::Play), on_play)
+        .listen(bindings.event(Action::Save), on_save)
+        .listen(bindings.event(Action::PrevScreen), on_prev_screen)
+        .listen(bindings.event(Action::NextScreen), on_next_screen)
+        .listen(InputEvent::MouseClick, on_mouse_click)
+        .listen(InputEvent::MouseRightClick, on_mouse_right_click)
+        .listen(InputEvent::MouseWheel, on_mouse_wheel);
+    add_header_handlers(inputs);
 }
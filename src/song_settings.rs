@@ -0,0 +1,219 @@
+use crate::{
+    controls::Action,
+    inputs::{
+        mouse,
+        Button::{Button1, Down, Left, Right, Up},
+        InputEvent, Inputs,
+    },
+    navigation::{add_header_handlers, go_to_pattern_list_screen},
+    notes::NOTES_PER_OCTAVE,
+    render::song_settings_input_at,
+    screen::Screen,
+    tracker::{tracker, PlayMode},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SongSettingsInput {
+    A4,
+    Temperament,
+    Key,
+    Scale,
+    EchoSource,
+    EchoTarget,
+    EchoDelay,
+    EchoVolume,
+    CustomCents(usize),
+}
+
+impl SongSettingsInput {
+    pub fn next(&self) -> Self {
+        match self {
+            SongSettingsInput::A4 => SongSettingsInput::Temperament,
+            SongSettingsInput::Temperament => SongSettingsInput::Key,
+            SongSettingsInput::Key => SongSettingsInput::Scale,
+            SongSettingsInput::Scale => SongSettingsInput::EchoSource,
+            SongSettingsInput::EchoSource => SongSettingsInput::EchoTarget,
+            SongSettingsInput::EchoTarget => SongSettingsInput::EchoDelay,
+            SongSettingsInput::EchoDelay => SongSettingsInput::EchoVolume,
+            SongSettingsInput::EchoVolume => SongSettingsInput::CustomCents(0),
+            &SongSettingsInput::CustomCents(pitch_class) => {
+                SongSettingsInput::CustomCents((pitch_class + 1).min(NOTES_PER_OCTAVE as usize - 1))
+            }
+        }
+    }
+
+    pub fn prev(&self) -> Self {
+        match self {
+            &a4 @ SongSettingsInput::A4 => a4,
+            SongSettingsInput::Temperament => SongSettingsInput::A4,
+            SongSettingsInput::Key => SongSettingsInput::Temperament,
+            SongSettingsInput::Scale => SongSettingsInput::Key,
+            SongSettingsInput::EchoSource => SongSettingsInput::Scale,
+            SongSettingsInput::EchoTarget => SongSettingsInput::EchoSource,
+            SongSettingsInput::EchoDelay => SongSettingsInput::EchoTarget,
+            SongSettingsInput::EchoVolume => SongSettingsInput::EchoDelay,
+            SongSettingsInput::CustomCents(0) => SongSettingsInput::EchoVolume,
+            &SongSettingsInput::CustomCents(pitch_class) => {
+                SongSettingsInput::CustomCents(pitch_class - 1)
+            }
+        }
+    }
+}
+
+// `coarse` is a B1+Up/Down change, fine changes come from B1+Left/Right and mouse drags
+unsafe fn update_focused_value(increase: bool, coarse: bool) {
+    let step = if coarse { 10 } else { 1 };
+    let tuning = tracker().tuning_mut();
+    match tracker().song_settings_focus() {
+        SongSettingsInput::A4 => tuning.update_a4(|a| {
+            if increase {
+                a.saturating_add(step)
+            } else {
+                a.saturating_sub(step)
+            }
+        }),
+        SongSettingsInput::Temperament => {
+            tuning.update_temperament(|a| if increase { a.next() } else { a.prev() })
+        }
+        SongSettingsInput::Key => tracker().update_key(|a| {
+            if increase {
+                a + 1
+            } else {
+                a + NOTES_PER_OCTAVE as u8 - 1
+            }
+        }),
+        SongSettingsInput::Scale => {
+            tracker().update_scale(|a| if increase { a.next() } else { a.prev() })
+        }
+        SongSettingsInput::EchoSource => {
+            if increase {
+                tracker().echo_mut().next_source()
+            } else {
+                tracker().echo_mut().prev_source()
+            }
+        }
+        SongSettingsInput::EchoTarget => {
+            tracker()
+                .echo_mut()
+                .update_target(|a| if increase { a.next() } else { a.prev() })
+        }
+        SongSettingsInput::EchoDelay => tracker().echo_mut().update_delay(|a| {
+            if increase {
+                a.saturating_add(1)
+            } else {
+                a.saturating_sub(1)
+            }
+        }),
+        SongSettingsInput::EchoVolume => tracker().echo_mut().update_volume(|a| {
+            if increase {
+                a.saturating_add(step as u8)
+            } else {
+                a.saturating_sub(step as u8)
+            }
+        }),
+        SongSettingsInput::CustomCents(pitch_class) => {
+            tuning.update_custom_cents(pitch_class, |a| {
+                if increase {
+                    a.saturating_add(step as i8)
+                } else {
+                    a.saturating_sub(step as i8)
+                }
+            })
+        }
+    }
+}
+
+fn on_button_down_press(_inputs: &Inputs) {
+    unsafe { tracker().song_settings_focus_next() }
+}
+
+fn on_button_up_press(_inputs: &Inputs) {
+    unsafe { tracker().song_settings_focus_prev() }
+}
+
+fn on_button_1_down_press(_inputs: &Inputs) {
+    unsafe { update_focused_value(false, true) }
+}
+
+fn on_button_1_up_press(_inputs: &Inputs) {
+    unsafe { update_focused_value(true, true) }
+}
+
+fn on_button_1_left_press(_inputs: &Inputs) {
+    unsafe { update_focused_value(false, false) }
+}
+
+fn on_button_1_right_press(_inputs: &Inputs) {
+    unsafe { update_focused_value(true, false) }
+}
+
+fn on_play(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().toggle_play(PlayMode::Song)
+        }
+    }
+}
+
+fn on_save(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            tracker().persist();
+        }
+    }
+}
+
+fn on_next_screen(inputs: &Inputs) {
+    unsafe {
+        if !inputs.is_repeat() {
+            go_to_pattern_list_screen(Screen::SongSettings);
+        }
+    }
+}
+
+fn on_mouse_click(_inputs: &Inputs) {
+    let (_, y) = mouse().position();
+    unsafe {
+        if let Some(input) = song_settings_input_at(tracker().song_settings_focus(), y) {
+            tracker().set_song_settings_focus(input);
+        }
+    }
+}
+
+fn on_mouse_drag(_inputs: &Inputs) {
+    let steps = mouse().drag_steps();
+    unsafe {
+        for _ in 0..steps.unsigned_abs() {
+            update_focused_value(steps > 0, false);
+        }
+    }
+}
+
+fn on_mouse_wheel(_inputs: &Inputs) {
+    unsafe {
+        for _ in 0..mouse().wheel_steps() {
+            tracker().song_settings_focus_next()
+        }
+        for _ in mouse().wheel_steps()..0 {
+            tracker().song_settings_focus_prev()
+        }
+    }
+}
+
+pub fn add_song_settings_screen_handlers(inputs: &mut Inputs) {
+    let bindings = unsafe { *tracker().bindings() };
+    inputs
+        .listen(InputEvent::Press(Down), on_button_down_press)
+        .listen(InputEvent::Press(Up), on_button_up_press)
+        .listen(InputEvent::Combo(Button1, Down), on_button_1_down_press)
+        .listen(InputEvent::Combo(Button1, Up), on_button_1_up_press)
+        .listen(InputEvent::Combo(Button1, Left), on_button_1_left_press)
+        .listen(InputEvent::Combo(Button1, Right), on_button_1_right_press)
+        .listen(bindings.event(Action::Play), on_play)
+        .listen(bindings.event(Action::Save), on_save)
+        .listen(bindings.event(Action::NextScreen), on_next_screen)
+        .listen(InputEvent::MouseClick, on_mouse_click)
+        .listen(InputEvent::MouseDrag, on_mouse_drag)
+        .listen(InputEvent::MouseWheel, on_mouse_wheel);
+    add_header_handlers(inputs);
+}
@@ -1,118 +1,203 @@
-use std::{collections::HashMap, time::Duration, vec};
-
-use crate::{unique_usize::get_unique_usize, wasm4::trace, wtime::Winstant};
-
-pub static mut TIMERS: Timers = Timers {
-    last_calls: None,
-    intervals: vec![],
-};
-
-#[derive(Eq, Hash, PartialEq)]
-pub enum ActionId {
-    Play,
-    Persist,
-    NavNextScreen,
-    NavPrevScreen,
-
-    InstrumentValueDownActionId,
-    InstrumentNextInputActionId,
-    InstrumentValueUpActionId,
-    InstrumentPrevInputActionId,
-    InstrumentValuePrevActionId,
-    InstrumentValueNextActionId,
-
-    PatternPitchOctaveDown,
-    PatternNavDown,
-    PatternPitchOctaveUp,
-    PatternNavUp,
-    PatternPitchUp,
-    PatternInstrumentNext,
-    PatternPitchDown,
-    PatternInstrumentPrev,
-
-    SongNextRow,
-    SongPrevRow,
-    SongDecrementPattern,
-    SongPrevChannel,
-    SongIncrementPattern,
-    SongNextChannel,
-    SongAddPattern,
+use std::vec;
+
+#[cfg(not(test))]
+static mut TIMERS: Timers = Timers::new();
+
+#[cfg(not(test))]
+pub unsafe fn timers() -> &'static mut Timers<'static> {
+    &mut *std::ptr::addr_of_mut!(TIMERS)
+}
+
+// Tests run on several threads, each one gets timers of its own
+#[cfg(test)]
+pub unsafe fn timers() -> &'static mut Timers<'static> {
+    std::thread_local! {
+        static TIMERS: *mut Timers<'static> = Box::into_raw(Box::new(Timers::new()));
+    }
+    &mut *TIMERS.with(|a| *a)
 }
 
 struct StoredInterval<'a> {
     id: usize,
     thunk: Box<dyn Fn() + 'a>,
-    interval: usize,
+    // Frames between runs, None for a timeout that runs once
+    interval: Option<usize>,
+    due_frame: usize,
 }
 
 pub struct Timers<'a> {
-    last_calls: Option<HashMap<ActionId, Winstant>>,
     intervals: Vec<StoredInterval<'a>>,
+    // Frames dispatched so far
+    frame: usize,
+    // Timers cancelled by a thunk while tick holds the list
+    cancelled: Vec<usize>,
+    dispatching: bool,
 }
 
-impl<'a> Timers<'a>  {
-    pub fn init(&mut self) {
-        self.last_calls = Some(HashMap::new());
+impl<'a> Timers<'a> {
+    pub const fn new() -> Self {
+        Timers {
+            intervals: vec![],
+            frame: 0,
+            cancelled: vec![],
+            dispatching: false,
+        }
     }
 
-    fn run_action<F>(&mut self, action_id: ActionId, action: F)
+    fn schedule<F>(&mut self, id: usize, action: F, frames: usize, interval: Option<usize>)
     where
-        F: FnOnce(),
+        F: Fn() + 'a,
     {
-        let now = Winstant::now();
-        let map = self
-            .last_calls
-            .as_mut()
-            .expect("Timers should be initialized");
-        map.insert(action_id, now);
-        action()
+        self.intervals.push(StoredInterval {
+            id,
+            thunk: Box::new(action),
+            interval,
+            // Counted from the last dispatched frame, so a one frame timer set by an
+            // input handler runs in the tick of the same update
+            due_frame: self.frame + frames.max(1),
+        });
     }
 
-    pub fn run_action_debounced<F>(&mut self, action_id: ActionId, t: Duration, action: F)
+    // Runs `action` every `interval` frames, the first time `interval` frames from now
+    pub fn set_interval<F>(&mut self, id: usize, action: F, interval: usize) -> usize
     where
-        F: FnOnce(),
+        F: Fn() + 'a,
     {
-        let now = Winstant::now();
-        let map = self
-            .last_calls
-            .as_ref()
-            .expect("Timers should be initialized");
-        let last_call = map.get(&action_id);
-        match last_call {
-            Some(last_call) if now > *last_call + t => self.run_action(action_id, action),
-            None => self.run_action(action_id, action),
-            _ => {}
-        }
+        self.schedule(id, action, interval, Some(interval.max(1)));
+        id
     }
 
-    pub fn set_interval<F>(&mut self, id: usize, action: F, interval: usize) -> usize
+    // Runs `action` once, `frames` frames from now
+    pub fn set_timeout<F>(&mut self, id: usize, action: F, frames: usize) -> usize
     where
-        F: Fn() + 'a
+        F: Fn() + 'a,
     {
-        self.intervals.push(StoredInterval {
-            id,
-            thunk: Box::new(action),
-            interval,
-        });
+        self.schedule(id, action, frames, None);
         id
     }
 
-    pub fn cancel_interval(&mut self, interval_id: usize) {
-        let mut i = 0;
-        while i < self.intervals.len() {
-            let x = &mut self.intervals[i];
-            if x.id == interval_id {
-                self.intervals.remove(i);
-            } else {
-                i += 1;
+    // Cancels intervals and timeouts, safe to call from a running thunk including
+    // the one being cancelled. While tick dispatches, the id only stops timers set
+    // before the cancel: one set again with the same id afterwards, e.g. a thunk
+    // that cancels and reschedules itself, is kept
+    pub fn cancel(&mut self, id: usize) {
+        self.intervals.retain(|a| a.id != id);
+        if self.dispatching {
+            self.cancelled.push(id);
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.frame += 1;
+        // Thunks may set and cancel timers, they see an empty list while the
+        // due ones run
+        let mut intervals = std::mem::take(&mut self.intervals);
+        self.dispatching = true;
+        for interval in intervals.iter_mut() {
+            if interval.due_frame > self.frame || self.cancelled.contains(&interval.id) {
+                continue;
+            }
+            if let Some(frames) = interval.interval {
+                interval.due_frame += frames;
+            }
+            (interval.thunk)();
+        }
+        self.dispatching = false;
+
+        let frame = self.frame;
+        let cancelled = std::mem::take(&mut self.cancelled);
+        intervals.retain(|a| {
+            !cancelled.contains(&a.id) && (a.interval.is_some() || a.due_frame > frame)
+        });
+        // Timers set by the thunks run after the ones that were already there
+        intervals.append(&mut self.intervals);
+        self.intervals = intervals;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, rc::Rc};
+
+    // Thunks reach their timers the way the cart's do through TIMERS
+    fn leaked_timers() -> *mut Timers<'static> {
+        Box::into_raw(Box::new(Timers::new()))
+    }
+
+    fn counter() -> Rc<Cell<u32>> {
+        Rc::new(Cell::new(0))
+    }
+
+    #[test]
+    fn runs_intervals_every_period_and_timeouts_once() {
+        let mut timers = Timers::new();
+        let (interval_runs, timeout_runs) = (counter(), counter());
+        let runs = interval_runs.clone();
+        timers.set_interval(1, move || runs.set(runs.get() + 1), 3);
+        let runs = timeout_runs.clone();
+        timers.set_timeout(2, move || runs.set(runs.get() + 1), 2);
+        for _ in 0..9 {
+            timers.tick();
+        }
+        assert_eq!(interval_runs.get(), 3);
+        assert_eq!(timeout_runs.get(), 1);
+    }
+
+    #[test]
+    fn cancels_a_due_timer_from_another_thunk() {
+        let timers = leaked_timers();
+        let runs = counter();
+        unsafe {
+            (*timers).set_interval(1, move || (*timers).cancel(2), 1);
+            let counted = runs.clone();
+            (*timers).set_interval(2, move || counted.set(counted.get() + 1), 1);
+            (*timers).tick();
+            (*timers).tick();
+        }
+        assert_eq!(runs.get(), 0);
+    }
+
+    #[test]
+    fn cancels_the_running_thunk() {
+        let timers = leaked_timers();
+        let runs = counter();
+        let counted = runs.clone();
+        unsafe {
+            (*timers).set_interval(
+                1,
+                move || {
+                    counted.set(counted.get() + 1);
+                    (*timers).cancel(1);
+                },
+                1,
+            );
+            for _ in 0..3 {
+                (*timers).tick();
             }
         }
+        assert_eq!(runs.get(), 1);
     }
 
-    pub fn tick(&self) {
-        for interval in &self.intervals {
-            let x = &interval.thunk;
-            x();
+    #[test]
+    fn keeps_a_timer_set_again_after_its_cancel() {
+        let timers = leaked_timers();
+        let runs = counter();
+        let counted = runs.clone();
+        unsafe {
+            (*timers).set_timeout(
+                1,
+                move || {
+                    (*timers).cancel(1);
+                    let counted = counted.clone();
+                    (*timers).set_timeout(1, move || counted.set(counted.get() + 1), 1);
+                },
+                1,
+            );
+            (*timers).tick();
+            (*timers).tick();
+            (*timers).tick();
         }
+        assert_eq!(runs.get(), 1);
     }
 }
@@ -1,11 +1,26 @@
-use std::{mem::size_of, ptr::addr_of};
+use std::{ptr::addr_of_mut, time::Duration};
 
 use crate::{
+    bits::{pack_bits, packed_size, unpack_bits, ByteReader},
     channel::Channel,
-    instrument::{DutyCycle, Instrument, InstrumentInput, MAX_INSTRUMENTS},
-    notes::{Note, NOTE_FREQ},
+    controls::{Action, Bindings, ControlsInput},
+    drums::{kit_key, Drum, KitParams, DEFAULT_DRUM_KIT, DRUM_KIT_SIZE},
+    echo::Echo,
+    instrument::{
+        DutyCycle, Instrument, InstrumentInput, Lfo, Pan, INSTRUMENT_NAME_LENGTH, MAX_INSTRUMENTS,
+    },
+    macros::{MacroKind, Sequence, MACRO_KINDS, MAX_SEQUENCE_LENGTH},
+    notes::{Command, Note, NOTES_PER_OCTAVE, NOTE_C3_INDEX, NOTE_FREQ},
+    players::{Player, PLAYERS},
+    presets::PRESETS,
+    scale::Scale,
     screen::{Screen, Screens},
-    wasm4::{diskr, diskw, tone, TONE_NOISE, TONE_PULSE1, TONE_PULSE2, TONE_TRIANGLE},
+    song_settings::SongSettingsInput,
+    timers::timers,
+    tuning::Tuning,
+    unique_usize::get_unique_usize,
+    wasm4::{diskr, diskw, tone},
+    wtime::Frames,
 };
 
 #[derive(PartialEq, Clone, Copy, Default)]
@@ -13,6 +28,8 @@ pub enum Column {
     #[default]
     Note,
     Instrument,
+    Command,
+    Chord,
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -21,9 +38,22 @@ pub struct Row {
     pulse2: Option<usize>,
     triangle: Option<usize>,
     noise: Option<usize>,
+    transpose: [i8; 4],
 }
 
-const MAX_PATTERNS: usize = 0x10;
+pub const MAX_PATTERNS: usize = 0x10;
+
+pub const MAX_TRANSPOSE: i8 = 0x0f;
+
+const TRANSPOSE_BITS: u32 = 5;
+
+const DRUM_BITS: u32 = 4;
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum SongColumn {
+    Pattern,
+    Transpose,
+}
 
 impl Row {
     pub fn channel(&self, channel: &Channel) -> &Option<usize> {
@@ -35,6 +65,19 @@ impl Row {
         }
     }
 
+    // Semitones added to the pitched notes of the cell's pattern during song playback
+    pub fn transpose(&self, channel: &Channel) -> i8 {
+        self.transpose[channel.index()]
+    }
+
+    pub fn update_transpose<F>(&mut self, channel: &Channel, f: F)
+    where
+        F: FnOnce(i8) -> i8,
+    {
+        let transpose = &mut self.transpose[channel.index()];
+        *transpose = f(*transpose).clamp(-MAX_TRANSPOSE, MAX_TRANSPOSE)
+    }
+
     pub fn set_channel_value(&mut self, channel: &Channel, value: Option<usize>) {
         match channel {
             Channel::Pulse1 => self.pulse1 = value,
@@ -80,21 +123,67 @@ impl Row {
         }
     }
 
-    pub fn to_bytes(&self, api_version: u8) -> Vec<u8> {
+    pub fn to_bytes(self, api_version: u8) -> [u8; 4] {
         match api_version {
-            1 => {
-                let mut v = vec![0_u8; 4];
-                v[0] = self.pulse1.unwrap_or(255).try_into().unwrap();
-                v[1] = self.pulse2.unwrap_or(255).try_into().unwrap();
-                v[2] = self.triangle.unwrap_or(255).try_into().unwrap();
-                v[3] = self.noise.unwrap_or(255).try_into().unwrap();
-                v
-            }
+            1 | 2 => [
+                self.pulse1.unwrap_or(255) as u8,
+                self.pulse2.unwrap_or(255) as u8,
+                self.triangle.unwrap_or(255) as u8,
+                self.noise.unwrap_or(255) as u8,
+            ],
             _ => panic!("Unsupported api version"),
         }
     }
 }
 
+// A note sounding on a channel. Notes of instruments with macros or LFOs are
+// driven by the player every frame, the rest are only timed here
+#[derive(Clone, Copy)]
+struct ActiveNote {
+    note: Note,
+    frame: u32,
+    // A note off brings the release forward
+    release_frame: u32,
+    end_frame: u32,
+    frame_driven: bool,
+    glide: Option<Glide>,
+    // Percent, echoes play quieter
+    volume: u8,
+    positions: [u8; MACRO_KINDS],
+}
+
+// Slide from the pitch of the channel's previous note, in cents from A4
+#[derive(Clone, Copy)]
+struct Glide {
+    from: i32,
+    frames: u32,
+}
+
+const FRAMES_PER_TICK: u32 = 8;
+
+const FULL_VOLUME: u8 = 100;
+
+// Scales both the peak and the sustain volume of a `tone` volume
+fn scale_volume(volume: u32, percent: u8) -> u32 {
+    let peak = (volume >> 8) * percent as u32 / FULL_VOLUME as u32;
+    let sustain = (volume & 0xff) * percent as u32 / FULL_VOLUME as u32;
+    peak << 8 | sustain
+}
+
+// `tone` duration of the first `frames` frames of an instrument's envelope
+fn envelope_start(instrument: &Instrument, frames: u32) -> u32 {
+    let attack = (instrument.attack() as u32).min(frames);
+    let decay = (instrument.decay() as u32).min(frames - attack);
+    attack << 24 | decay << 16 | (frames - attack - decay)
+}
+
+// A note waiting to be replayed by the echo
+#[derive(Clone, Copy)]
+struct EchoNote {
+    note: Note,
+    frames_left: u32,
+}
+
 pub enum PlayMode {
     Song,
     Pattern,
@@ -110,22 +199,64 @@ type Song = [Row; SONG_SIZE];
 pub struct Tracker {
     frame: u32,
     tick: u8,
-    patterns: Vec<[Option<Note>; 16]>, // save - 2b note * 16 * MAX_PATTERNS = 2 * 16 * 16 = 512b
-    cursor_tick: u8,
+    patterns: Vec<[Option<Note>; 16]>, // save - 2b mask * MAX_PATTERNS + 2b note * notes count
     play: PlayMode,
     selected_column: Column,
     screens: Screens,
-    instruments: [Instrument; MAX_INSTRUMENTS], // save - 7 * 32 = 224b
+    instruments: Vec<Instrument>, // save - 8 * 32 = 256b + names 4b + 36 bits per name + macros
     selected_instrument_index: usize,
     instrument_focus: InstrumentInput,
-    selected_channel: Channel,
+    instrument_name_cursor: usize,
+    // Kit key edited on the instrument screen
+    drum_key: usize,
+    instrument_clipboard: Option<Instrument>,
+    selected_preset: usize,
     song_cursor_row_index: usize,
-    song: Song, // save Song.len() * 4
+    song_column: SongColumn,
+    song: Song, // save Song.len() * 4 + transposes
     selected_pattern: usize,
     song_tick: usize,
+    macro_cursor_kind: MacroKind,
+    macro_cursor_step: usize,
+    active_notes: [Option<ActiveNote>; 4],
+    last_pitches: [Option<i32>; 4],
+    echo_notes: Vec<EchoNote>,
+    tuning: Tuning, // save 15b
+    echo: Echo,     // save 3b
+    song_settings_focus: SongSettingsInput,
+    bindings: Bindings, // save 4b
+    controls_focus: ControlsInput,
+    key: u8,
+    scale: Scale, // save 1b
+    // Notes played on the gamepad are written at the playing step
+    recording: bool,
+    record_note_index: usize,
+    // The first player's cursor is the editing cursor of the screens
+    players: [Player; PLAYERS],
+    // Edited notes and instruments are played right away
+    preview: bool, // save 1b
+    // A line shown at the bottom of every screen and the timeout that hides it
+    notice: Option<(&'static [u8], usize)>,
 }
 
-const STORAGE_LAYOUT_VERSION: u8 = 1;
+const NOTICE_FRAMES: usize = 120;
+
+const MACROS_FULL_NOTICE: &[u8] = b"Macro memory full";
+
+const DISK_FULL_NOTICE: &[u8] = b"Disk full";
+
+// On top of the disk budget the macros section has its own cap, so a few
+// instruments can't take the room the rest of the song needs
+const MACRO_SECTION_SIZE: usize = 128;
+
+const STORAGE_LAYOUT_VERSION: u8 = 2;
+
+const DISK_SIZE: usize = 1024;
+
+const NAME_CHAR_BITS: usize = 6;
+
+const DEFAULT_INSTRUMENT: Instrument =
+    Instrument::new(DutyCycle::Eighth, 0, 0, 0x0f, 0x0f, 0x64, 0x64, 0);
 
 impl Tracker {
     const fn empty() -> Self {
@@ -133,30 +264,56 @@ impl Tracker {
             frame: 0,
             tick: 0,
             patterns: vec![],
-            cursor_tick: 0,
             play: PlayMode::Idle,
             selected_column: Column::Note,
-            instruments: [Instrument::new(DutyCycle::Eighth, 0, 0, 0x0f, 0x0f, 0x64, 0x64, 0);
-                MAX_INSTRUMENTS],
+            instruments: vec![],
             screens: Screens::Single(Screen::Pattern),
             selected_instrument_index: 0,
             instrument_focus: InstrumentInput::DutyCycle,
-            selected_channel: Channel::Pulse1,
+            instrument_name_cursor: 0,
+            drum_key: 0,
+            instrument_clipboard: None,
+            selected_preset: 0,
             song_cursor_row_index: 0,
+            song_column: SongColumn::Pattern,
             song: [Row {
                 pulse1: None,
                 pulse2: None,
                 triangle: None,
                 noise: None,
+                transpose: [0; 4],
             }; SONG_SIZE],
             selected_pattern: 0,
             song_tick: 0,
+            macro_cursor_kind: MacroKind::Volume,
+            macro_cursor_step: 0,
+            active_notes: [None; 4],
+            last_pitches: [None; 4],
+            echo_notes: vec![],
+            tuning: Tuning::new(),
+            echo: Echo::new(),
+            song_settings_focus: SongSettingsInput::A4,
+            bindings: Bindings::new(),
+            controls_focus: ControlsInput::Binding(Action::Play),
+            key: 0,
+            scale: Scale::Chromatic,
+            recording: false,
+            record_note_index: NOTE_C3_INDEX,
+            players: [
+                Player::first(),
+                Player::new(Channel::Pulse2),
+                Player::new(Channel::Triangle),
+                Player::new(Channel::Noise),
+            ],
+            preview: true,
+            notice: None,
         }
     }
 
     pub fn new() -> Self {
         Tracker {
             patterns: vec![[None; 16]; MAX_PATTERNS],
+            instruments: vec![DEFAULT_INSTRUMENT; MAX_INSTRUMENTS],
             ..Tracker::empty()
         }
     }
@@ -169,81 +326,366 @@ impl Tracker {
         &self.screens
     }
 
-    fn play_tick(&self) {
-        match self.play {
-            PlayMode::Song => {
-                let pattern_index: usize = self.tick.into();
-                let song = self.song;
-                let row = song[self.song_tick];
-                if let Some(note) = row.pulse1.and_then(|pulse1_pattern_index| {
-                    self.patterns[pulse1_pattern_index][pattern_index]
-                }) {
-                    let instrument = self.instruments[note.instrument];
-                    let duty_cycle = instrument.duty_cycle().to_flag();
-                    tone(
-                        instrument.get_frequency(note),
-                        instrument.get_duration(),
-                        instrument.get_volume(),
-                        TONE_PULSE1 | duty_cycle,
-                    );
-                }
+    fn note_pan(&self, note: Note) -> Pan {
+        match note.command() {
+            Command::Pan(pan) => pan,
+            _ => self.instruments[note.instrument].pan(),
+        }
+    }
+
+    // Drum kits only play drums on the noise channel
+    fn channel_drum(&self, note: Note, channel: Channel) -> Option<Drum> {
+        self.note_drum(note).filter(|_| channel == Channel::Noise)
+    }
 
-                if let Some(note) = row.pulse2.and_then(|pulse2_pattern_index| {
-                    self.patterns[pulse2_pattern_index][pattern_index]
-                }) {
-                    let instrument = self.instruments[note.instrument];
-                    let duty_cycle = instrument.duty_cycle().to_flag();
-                    tone(
-                        instrument.get_frequency(note),
-                        instrument.get_duration(),
-                        instrument.get_volume(),
-                        TONE_PULSE2 | duty_cycle,
-                    );
+    fn is_drum_note(&self, note: Note, channel: Channel) -> bool {
+        self.channel_drum(note, channel).is_some()
+    }
+
+    // Frequency, volume and flags of a note played with the hardware envelope
+    fn note_tone(&self, note: Note, channel: Channel, volume: u8) -> (u32, u32, u32) {
+        let instrument = self.instruments[note.instrument];
+        let pan = self.note_pan(note).to_flag();
+        if let Some(drum) = self.channel_drum(note, channel) {
+            (
+                drum.frequency() as u32,
+                scale_volume(drum.volume() as u32, volume),
+                channel.to_flag() | pan,
+            )
+        } else {
+            (
+                self.tuning.frequency(note.index, instrument.finetune()),
+                scale_volume(instrument.volume() as u32, volume),
+                channel.to_flag() | instrument.duty_cycle().to_flag() | pan,
+            )
+        }
+    }
+
+    fn play_note(&mut self, note: Note, channel: Channel, volume: u8) {
+        if note.is_note_cut() {
+            self.cut_note(channel);
+            return;
+        }
+        if note.is_note_off() {
+            self.release_note(channel);
+            return;
+        }
+        if note.command() == Command::Legato && self.retune_note(note, channel) {
+            return;
+        }
+
+        let instrument = self.instruments[note.instrument];
+        if let Some(drum) = self.channel_drum(note, channel) {
+            self.active_notes[channel.index()] = Some(ActiveNote {
+                note,
+                frame: 0,
+                release_frame: drum.held_frames(),
+                end_frame: drum.held_frames() + drum.release() as u32,
+                frame_driven: false,
+                glide: None,
+                volume,
+                positions: [0; MACRO_KINDS],
+            });
+            tone(
+                drum.get_frequency(),
+                drum.get_duration(),
+                scale_volume(drum.get_volume(), volume),
+                channel.to_flag() | self.note_pan(note).to_flag(),
+            );
+            return;
+        }
+
+        let pitch = self.tuning.pitch(note.index, instrument.finetune());
+        let glide_ticks = match note.command() {
+            Command::Glide => instrument.glide().max(1),
+            _ => instrument.glide(),
+        };
+        let glide = match self.last_pitches[channel.index()] {
+            Some(from) if glide_ticks > 0 => Some(Glide {
+                from,
+                frames: glide_ticks as u32 * FRAMES_PER_TICK,
+            }),
+            _ => None,
+        };
+        self.last_pitches[channel.index()] = Some(pitch);
+
+        let frame_driven = instrument.has_macros() || instrument.has_lfo() || note.has_chord();
+        self.active_notes[channel.index()] = Some(ActiveNote {
+            note,
+            frame: 0,
+            release_frame: instrument.held_frames(),
+            end_frame: instrument.held_frames() + instrument.release() as u32,
+            frame_driven,
+            glide,
+            volume,
+            positions: [0; MACRO_KINDS],
+        });
+        if frame_driven {
+            return;
+        }
+
+        let duty_cycle = instrument.duty_cycle().to_flag();
+        let flags = channel.to_flag() | duty_cycle | self.note_pan(note).to_flag();
+        // The sweep slides to the note over the glide with the start of the
+        // envelope, the rest of the note is issued once the glide is over
+        if let Some(glide) = glide {
+            let end_frame = instrument.held_frames() + instrument.release() as u32;
+            let (to, duration) = if glide.frames < end_frame {
+                (pitch, envelope_start(&instrument, glide.frames))
+            } else {
+                // The note ends first, halfway to its pitch
+                let reached =
+                    glide.from + (pitch - glide.from) * end_frame as i32 / glide.frames as i32;
+                (reached, instrument.get_duration())
+            };
+            tone(
+                self.tuning.pitch_frequency(glide.from) | self.tuning.pitch_frequency(to) << 16,
+                duration,
+                scale_volume(instrument.get_volume(), volume),
+                flags,
+            );
+            return;
+        }
+
+        // A glide replaces the note sweep, also with no previous note to slide from
+        let frequency = if glide_ticks > 0 {
+            self.tuning.pitch_frequency(pitch)
+        } else {
+            instrument.get_frequency(note, &self.tuning)
+        };
+        tone(
+            frequency,
+            instrument.get_duration(),
+            scale_volume(instrument.get_volume(), volume),
+            flags,
+        );
+    }
+
+    fn cut_note(&mut self, channel: Channel) {
+        if let Some(active_note) = self.active_notes[channel.index()].take() {
+            if !active_note.frame_driven {
+                let (frequency, _, flags) =
+                    self.note_tone(active_note.note, channel, active_note.volume);
+                tone(frequency, 1, 0, flags);
+            }
+        }
+    }
+
+    fn release_note(&mut self, channel: Channel) {
+        let active_note = match self.active_notes[channel.index()] {
+            Some(active_note) if active_note.frame < active_note.release_frame => active_note,
+            _ => return,
+        };
+        let release = match self.channel_drum(active_note.note, channel) {
+            Some(drum) => drum.release(),
+            None => self.instruments[active_note.note.instrument].release(),
+        };
+        self.active_notes[channel.index()] = Some(ActiveNote {
+            release_frame: active_note.frame,
+            end_frame: active_note.frame + release as u32,
+            ..active_note
+        });
+        if !active_note.frame_driven {
+            let (frequency, volume, flags) =
+                self.note_tone(active_note.note, channel, active_note.volume);
+            tone(frequency, (release as u32) << 8, volume, flags);
+        }
+    }
+
+    // Hardware envelopes can't be retuned, so the rest of such a note is
+    // re-issued at the new pitch, skipping attack and decay
+    fn retune_note(&mut self, note: Note, channel: Channel) -> bool {
+        let active_note = match self.active_notes[channel.index()] {
+            Some(active_note) => active_note,
+            None => return false,
+        };
+        let active_note = ActiveNote {
+            note: Note {
+                index: note.index,
+                ..active_note.note
+            },
+            glide: None,
+            ..active_note
+        };
+        self.active_notes[channel.index()] = Some(active_note);
+        if !self.is_drum_note(active_note.note, channel) {
+            let finetune = self.instruments[active_note.note.instrument].finetune();
+            self.last_pitches[channel.index()] = Some(self.tuning.pitch(note.index, finetune));
+        }
+        if !active_note.frame_driven {
+            self.play_rest_of_note(active_note, channel);
+        }
+        true
+    }
+
+    fn play_rest_of_note(&self, active_note: ActiveNote, channel: Channel) {
+        let duration = if active_note.frame < active_note.release_frame {
+            (active_note.release_frame - active_note.frame)
+                | ((active_note.end_frame - active_note.release_frame) << 8)
+        } else {
+            (active_note.end_frame - active_note.frame) << 8
+        };
+        let (frequency, volume, flags) =
+            self.note_tone(active_note.note, channel, active_note.volume);
+        tone(frequency, duration, volume, flags);
+    }
+
+    // Macro and LFO notes are re-issued as one frame long tones at the volume
+    // their ADSR envelope has reached, the release fades from the volume the
+    // note was let go at. Notes of other instruments just have their frames counted
+    fn play_active_notes(&mut self) {
+        for channel in Channel::iterator() {
+            let active_note = match self.active_notes[channel.index()] {
+                Some(active_note) => active_note,
+                None => continue,
+            };
+            if active_note.frame >= active_note.end_frame {
+                self.active_notes[channel.index()] = None;
+                continue;
+            }
+            if !active_note.frame_driven {
+                let glide_over = active_note.glide.map(|a| a.frames) == Some(active_note.frame);
+                if glide_over {
+                    self.play_rest_of_note(active_note, channel);
                 }
+                self.active_notes[channel.index()] = Some(ActiveNote {
+                    frame: active_note.frame + 1,
+                    ..active_note
+                });
+                continue;
+            }
 
-                if let Some(note) = row.triangle.and_then(|triangle_pattern_index| {
-                    self.patterns[triangle_pattern_index][pattern_index]
-                }) {
-                    let instrument = self.instruments[note.instrument];
-                    let duty_cycle = instrument.duty_cycle().to_flag();
-                    tone(
-                        instrument.get_frequency(note),
-                        instrument.get_duration(),
-                        instrument.get_volume(),
-                        TONE_TRIANGLE | duty_cycle,
-                    );
+            let note = active_note.note;
+            let instrument = self.instruments[note.instrument];
+            let released = active_note.frame >= active_note.release_frame;
+
+            let value = |kind: MacroKind| {
+                instrument
+                    .macro_sequence(kind)
+                    .value_at(active_note.positions[kind.index()])
+            };
+
+            let envelope = if released {
+                let release = active_note.end_frame - active_note.release_frame;
+                instrument.held_volume_at(active_note.release_frame)
+                    * (active_note.end_frame - active_note.frame)
+                    / release
+            } else {
+                instrument.held_volume_at(active_note.frame)
+            };
+            let volume = match value(MacroKind::Volume) {
+                Some(multiplier) => envelope * multiplier as u32 / 15,
+                None => envelope,
+            };
+            let volume = volume * instrument.tremolo_at(active_note.frame) / 100;
+            let volume = scale_volume(volume, active_note.volume);
+            let arpeggio = value(MacroKind::Arpeggio).unwrap_or(0) as i32
+                + note.chord_offset(active_note.frame) as i32;
+            let note_index = (note.index as i32 + arpeggio).clamp(0, NOTE_FREQ.len() as i32 - 1);
+            let pitch = self
+                .tuning
+                .pitch(note_index as usize, instrument.finetune())
+                + instrument.vibrato_at(active_note.frame);
+            // Glides move linearly in cents, so every semitone takes the same time
+            let pitch = match active_note.glide {
+                Some(glide) if active_note.frame < glide.frames => {
+                    let target = self.tuning.pitch(note.index, instrument.finetune());
+                    let remaining = (glide.frames - active_note.frame) as i32;
+                    pitch + (glide.from - target) * remaining / glide.frames as i32
                 }
+                _ => pitch,
+            };
+            let frequency = self.tuning.pitch_frequency(pitch) as i32
+                + value(MacroKind::Pitch).unwrap_or(0) as i32;
+            let frequency = frequency.max(1) as u32;
+            let duty_cycle = match value(MacroKind::Duty) {
+                Some(bits) => DutyCycle::from_bits(bits as u8),
+                None => instrument.duty_cycle(),
+            };
+
+            tone(
+                frequency,
+                1,
+                volume,
+                channel.to_flag() | duty_cycle.to_flag() | self.note_pan(note).to_flag(),
+            );
 
-                if let Some(note) = row.noise.and_then(|noise_pattern_index| {
-                    self.patterns[noise_pattern_index][pattern_index]
-                }) {
-                    let instrument = self.instruments[note.instrument];
-                    let duty_cycle = instrument.duty_cycle().to_flag();
-                    tone(
-                        instrument.get_frequency(note),
-                        instrument.get_duration(),
-                        instrument.get_volume(),
-                        TONE_NOISE | duty_cycle,
-                    );
+            let mut positions = active_note.positions;
+            for kind in MacroKind::iterator() {
+                positions[kind.index()] = instrument
+                    .macro_sequence(kind)
+                    .next_position(positions[kind.index()], released);
+            }
+            self.active_notes[channel.index()] = Some(ActiveNote {
+                frame: active_note.frame + 1,
+                positions,
+                ..active_note
+            });
+        }
+    }
+
+    fn trigger_note(&mut self, note: Note, channel: Channel) {
+        self.play_note(note, channel, FULL_VOLUME);
+        if self.echo.source() == Some(channel) && self.echo.target() != channel {
+            self.echo_notes.push(EchoNote {
+                note,
+                frames_left: self.echo.delay() as u32 * FRAMES_PER_TICK,
+            });
+        }
+    }
+
+    fn play_echoes(&mut self) {
+        let target = self.echo.target();
+        let volume = self.echo.volume();
+        let mut due = vec![];
+        self.echo_notes.retain_mut(|echo_note| {
+            echo_note.frames_left -= 1;
+            if echo_note.frames_left == 0 {
+                due.push(echo_note.note);
+            }
+            echo_note.frames_left > 0
+        });
+        for note in due {
+            self.play_note(note, target, volume);
+        }
+    }
+
+    fn play_tick(&mut self) {
+        match self.play {
+            PlayMode::Song => {
+                let pattern_index: usize = self.tick.into();
+                let row = self.song[self.song_tick];
+                for channel in Channel::iterator() {
+                    if let Some(note) = row.channel(&channel).and_then(|channel_pattern_index| {
+                        self.patterns[channel_pattern_index][pattern_index]
+                    }) {
+                        // Transposing a drum kit would swap its drums
+                        let note = if self.is_drum_note(note, channel) {
+                            note
+                        } else {
+                            note.transposed(row.transpose(&channel))
+                        };
+                        self.trigger_note(note, channel);
+                    }
                 }
             }
             PlayMode::Pattern => {
                 let pattern_index: usize = self.tick.into();
-                if let Some(note) = self.patterns[self.selected_pattern][pattern_index] {
-                    let instrument = self.instruments[note.instrument];
-                    let duty_cycle = instrument.duty_cycle().to_flag();
-                    let channel = self.selected_channel;
-                    tone(
-                        instrument.get_frequency(note),
-                        instrument.get_duration(),
-                        instrument.get_volume(),
-                        match channel {
-                            Channel::Pulse1 => TONE_PULSE1,
-                            Channel::Pulse2 => TONE_PULSE2,
-                            Channel::Triangle => TONE_TRIANGLE,
-                            Channel::Noise => TONE_NOISE,
-                        } | duty_cycle,
-                    );
+                // Every player hears the pattern they edit, the first one wins a
+                // shared channel
+                let mut channels = vec![];
+                for player in 0..PLAYERS {
+                    let channel = self.players[player].channel();
+                    if !self.players[player].is_active() || channels.contains(&channel) {
+                        continue;
+                    }
+                    channels.push(channel);
+                    if let Some(note) = self
+                        .player_pattern(player)
+                        .and_then(|pattern| self.patterns[pattern][pattern_index])
+                    {
+                        self.trigger_note(note, channel);
+                    }
                 }
             }
             PlayMode::Idle => {}
@@ -252,7 +694,13 @@ impl Tracker {
 
     pub fn toggle_play(&mut self, mode: PlayMode) {
         match self.play {
-            PlayMode::Song | PlayMode::Pattern => self.play = PlayMode::Idle,
+            PlayMode::Song | PlayMode::Pattern => {
+                self.recording = false;
+                self.active_notes = [None; 4];
+                self.last_pitches = [None; 4];
+                self.echo_notes.clear();
+                self.play = PlayMode::Idle
+            }
             PlayMode::Idle => {
                 self.song_tick = 0;
                 self.tick = 0;
@@ -264,12 +712,16 @@ impl Tracker {
 
     pub fn update(&mut self) {
         if let PlayMode::Idle = self.play {
+            // Previewed notes still need their envelopes and macros
+            self.play_active_notes();
             return;
         }
 
         if self.frame == 0 {
             self.play_tick();
         }
+        self.play_echoes();
+        self.play_active_notes();
         self.frame = if self.frame == 7 {
             self.tick = if self.tick == 15 {
                 if let PlayMode::Song = self.play {
@@ -289,20 +741,185 @@ impl Tracker {
         };
     }
 
+    pub fn is_preview_enabled(&self) -> bool {
+        self.preview
+    }
+
+    pub fn toggle_preview(&mut self) {
+        self.preview = !self.preview
+    }
+
+    pub fn preview_note(&mut self, note: Note, channel: Channel) {
+        if self.preview {
+            self.play_note(note, channel, FULL_VOLUME);
+        }
+    }
+
+    pub fn preview_current_note(&mut self) {
+        if let Some(note) = *self.current_note() {
+            self.preview_note(note, self.selected_channel());
+        }
+    }
+
+    // A C3 played with the selected instrument
+    pub fn preview_selected_instrument(&mut self) {
+        let note = Note {
+            instrument: self.selected_instrument_index,
+            ..Note::new()
+        };
+        match self.instrument_focus {
+            // Kit edits are heard as the drum of the edited key
+            InstrumentInput::DrumKey
+            | InstrumentInput::Drum
+            | InstrumentInput::KitFrequency
+            | InstrumentInput::KitSweep
+            | InstrumentInput::KitEnvelope
+            | InstrumentInput::KitVolume => {
+                let note = Note {
+                    index: NOTE_C3_INDEX + self.drum_key,
+                    ..note
+                };
+                self.preview_note(note, Channel::Noise)
+            }
+            _ => self.preview_note(note, self.selected_channel()),
+        }
+    }
+
+    pub fn drum_key(&self) -> usize {
+        self.drum_key
+    }
+
+    pub fn update_drum_key<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.drum_key = (f(self.drum_key as u8) as usize).min(DRUM_KIT_SIZE - 1)
+    }
+
+    // Drum a note plays, None unless it's a key of a drum kit instrument
+    pub fn note_drum(&self, note: Note) -> Option<Drum> {
+        let instrument = &self.instruments[note.instrument];
+        if !instrument.is_drum_kit() {
+            return None;
+        }
+        kit_key(note.index).map(|key| instrument.kit_drum(key))
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    // Recording runs on top of the pattern loop and starts it when needed
+    pub fn toggle_recording(&mut self) {
+        if self.recording {
+            self.recording = false;
+            return;
+        }
+        if let PlayMode::Idle = self.play {
+            self.toggle_play(PlayMode::Pattern);
+        }
+        if let PlayMode::Pattern = self.play {
+            self.recording = true;
+        }
+    }
+
+    pub fn record_note(&self) -> Note {
+        Note {
+            index: self.record_note_index,
+            instrument: self.selected_instrument_index,
+            ..Note::new()
+        }
+    }
+
+    // Steps through the notes of the song scale, `degrees` can be negative
+    pub fn step_record_note(&mut self, degrees: i32) {
+        for _ in 0..degrees.unsigned_abs() {
+            let next = if degrees > 0 {
+                self.scale.next_note(self.key, Some(self.record_note_index))
+            } else {
+                self.scale.prev_note(self.key, self.record_note_index)
+            };
+            match next {
+                Some(index) => self.record_note_index = index,
+                None => return,
+            }
+        }
+    }
+
+    pub fn step_record_octave(&mut self, increase: bool) {
+        let mut note = self.record_note();
+        if increase {
+            note.increase_octave()
+        } else {
+            note.decrease_octave()
+        }
+        self.record_note_index = note.index;
+    }
+
+    // Writes the record note at the step closest to the playhead
+    pub fn commit_record_note(&mut self) {
+        let pattern_frames = 16 * FRAMES_PER_TICK;
+        // The playing tick already advanced when its first frame is still to come
+        let elapsed =
+            (self.tick as u32 * FRAMES_PER_TICK + self.frame + pattern_frames - 1) % pattern_frames;
+        let step = (elapsed + FRAMES_PER_TICK / 2) / FRAMES_PER_TICK % 16;
+        let note = self.record_note();
+        self.players[0].set_tick(step as u8);
+        self.set_current_note(&Some(note));
+        // A step that already played would only sound on the next loop
+        if step * FRAMES_PER_TICK <= elapsed {
+            self.trigger_note(note, self.selected_channel());
+        }
+    }
+
+    pub fn players(&self) -> &[Player; PLAYERS] {
+        &self.players
+    }
+
+    pub fn player_mut(&mut self, player: usize) -> &mut Player {
+        &mut self.players[player]
+    }
+
+    // Pattern of the player's channel on the song cursor row, the first player
+    // edits the selected pattern
+    pub fn player_pattern(&self, player: usize) -> Option<usize> {
+        if player == 0 {
+            return Some(self.selected_pattern);
+        }
+        let channel = self.players[player].channel();
+        *self.song[self.song_cursor_row_index].channel(&channel)
+    }
+
+    pub fn player_note_mut(&mut self, player: usize) -> Option<&mut Option<Note>> {
+        let tick = self.players[player].tick() as usize;
+        self.player_pattern(player)
+            .map(|pattern| &mut self.patterns[pattern][tick])
+    }
+
+    // Undoes an edit of the player's note that made the save too big for the disk
+    pub fn fit_player_note(&mut self, player: usize, before: Option<Note>) {
+        if !self.fits_on_disk() {
+            if let Some(note) = self.player_note_mut(player) {
+                *note = before;
+            }
+            self.notify(DISK_FULL_NOTICE);
+        }
+    }
+
     pub fn cursor_tick(&self) -> u8 {
-        self.cursor_tick
+        self.players[0].tick()
     }
 
     pub fn saturating_increase_cursor_tick(&mut self) {
-        if self.cursor_tick < 15 {
-            self.cursor_tick += 1
-        }
+        self.players[0].saturating_increase_tick()
     }
 
     pub fn saturating_decrease_cursor_tick(&mut self) {
-        if self.cursor_tick != 0 {
-            self.cursor_tick -= 1
-        }
+        self.players[0].saturating_decrease_tick()
+    }
+
+    pub fn set_cursor_tick(&mut self, tick: u8) {
+        self.players[0].set_tick(tick)
     }
 
     pub fn selected_column(&self) -> Column {
@@ -333,12 +950,199 @@ impl Tracker {
         self.selected_instrument_index = index;
     }
 
+    pub fn next_selected_instrument_index(&mut self, step: usize) {
+        self.selected_instrument_index =
+            (self.selected_instrument_index + step).min(MAX_INSTRUMENTS - 1)
+    }
+
+    pub fn prev_selected_instrument_index(&mut self, step: usize) {
+        self.selected_instrument_index = self.selected_instrument_index.saturating_sub(step)
+    }
+
+    pub fn instrument(&self, index: usize) -> &Instrument {
+        &self.instruments[index]
+    }
+
+    pub fn is_instrument_used(&self, index: usize) -> bool {
+        self.patterns
+            .iter()
+            .flatten()
+            .any(|note| matches!(note, Some(note) if note.instrument_index() == index))
+    }
+
+    pub fn copy_selected_instrument(&mut self) {
+        self.instrument_clipboard = Some(*self.selected_instrument())
+    }
+
+    pub fn paste_selected_instrument(&mut self) {
+        if let Some(instrument) = self.instrument_clipboard {
+            let replaced = *self.selected_instrument();
+            *self.selected_instrument_mut() = instrument;
+            if self.macro_section_size() > MACRO_SECTION_SIZE {
+                *self.selected_instrument_mut() = replaced;
+                self.notify(MACROS_FULL_NOTICE);
+            } else {
+                self.fit_selected_instrument(replaced);
+            }
+        }
+    }
+
+    // Undoes an edit of the selected instrument that made the save too big for the disk
+    pub fn fit_selected_instrument(&mut self, before: Instrument) {
+        if !self.fits_on_disk() {
+            *self.selected_instrument_mut() = before;
+            self.notify(DISK_FULL_NOTICE);
+        }
+    }
+
+    // Size of the saved macros section, see persist
+    fn macro_section_size(&self) -> usize {
+        let sequences = self.instruments.iter().flat_map(|instrument| {
+            MacroKind::iterator().map(move |kind| instrument.macro_sequence(kind))
+        });
+        1 + sequences
+            .filter(|sequence| !sequence.is_empty())
+            .map(|sequence| 4 + sequence.len())
+            .sum::<usize>()
+    }
+
+    // Edits that would overflow the macros section or the disk are undone
+    pub fn update_selected_macro<F>(&mut self, kind: MacroKind, f: F)
+    where
+        F: FnOnce(&mut Sequence),
+    {
+        let instrument = *self.selected_instrument();
+        self.selected_instrument_mut().update_macro(kind, f);
+        if self.macro_section_size() > MACRO_SECTION_SIZE {
+            *self.selected_instrument_mut() = instrument;
+            self.notify(MACROS_FULL_NOTICE);
+        } else {
+            self.fit_selected_instrument(instrument);
+        }
+    }
+
+    pub fn reset_selected_instrument(&mut self) {
+        *self.selected_instrument_mut() = DEFAULT_INSTRUMENT
+    }
+
+    pub fn selected_preset(&self) -> &Instrument {
+        &PRESETS[self.selected_preset]
+    }
+
+    pub fn next_preset(&mut self) {
+        if self.selected_preset < PRESETS.len() - 1 {
+            self.selected_preset += 1
+        }
+    }
+
+    pub fn prev_preset(&mut self) {
+        if self.selected_preset != 0 {
+            self.selected_preset -= 1
+        }
+    }
+
+    pub fn load_selected_preset(&mut self) {
+        *self.selected_instrument_mut() = PRESETS[self.selected_preset]
+    }
+
+    pub fn echo(&self) -> &Echo {
+        &self.echo
+    }
+
+    pub fn echo_mut(&mut self) -> &mut Echo {
+        &mut self.echo
+    }
+
+    pub fn tuning(&self) -> &Tuning {
+        &self.tuning
+    }
+
+    pub fn tuning_mut(&mut self) -> &mut Tuning {
+        &mut self.tuning
+    }
+
+    pub fn song_settings_focus(&self) -> SongSettingsInput {
+        self.song_settings_focus
+    }
+
+    pub fn set_song_settings_focus(&mut self, input: SongSettingsInput) {
+        self.song_settings_focus = input
+    }
+
+    pub fn song_settings_focus_next(&mut self) {
+        self.song_settings_focus = self.song_settings_focus.next()
+    }
+
+    pub fn song_settings_focus_prev(&mut self) {
+        self.song_settings_focus = self.song_settings_focus.prev()
+    }
+
+    // Drum kits map pitches to drums, their notes are never held to the scale
+    pub fn scale_for(&self, note: Note, channel: Channel) -> Scale {
+        if self.is_drum_note(note, channel) {
+            Scale::Chromatic
+        } else {
+            self.scale
+        }
+    }
+
+    pub fn key(&self) -> u8 {
+        self.key
+    }
+
+    pub fn update_key<F>(&mut self, f: F)
+    where
+        F: FnOnce(u8) -> u8,
+    {
+        self.key = f(self.key) % NOTES_PER_OCTAVE as u8
+    }
+
+    pub fn scale(&self) -> Scale {
+        self.scale
+    }
+
+    pub fn update_scale<F>(&mut self, f: F)
+    where
+        F: FnOnce(Scale) -> Scale,
+    {
+        self.scale = f(self.scale)
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn bindings_mut(&mut self) -> &mut Bindings {
+        &mut self.bindings
+    }
+
+    pub fn controls_focus(&self) -> ControlsInput {
+        self.controls_focus
+    }
+
+    pub fn set_controls_focus(&mut self, input: ControlsInput) {
+        self.controls_focus = input
+    }
+
+    pub fn controls_focus_next(&mut self) {
+        self.controls_focus = self.controls_focus.next()
+    }
+
+    pub fn controls_focus_prev(&mut self) {
+        self.controls_focus = self.controls_focus.prev()
+    }
+
     pub fn instrument_focus(&self) -> InstrumentInput {
         self.instrument_focus
     }
 
+    pub fn set_instrument_focus(&mut self, input: InstrumentInput) {
+        self.instrument_focus = input
+    }
+
     pub fn instrument_focus_next(&mut self) {
         self.instrument_focus = match self.instrument_focus {
+            InstrumentInput::Name => InstrumentInput::DutyCycle,
             InstrumentInput::DutyCycle => InstrumentInput::Attack,
             InstrumentInput::Attack => InstrumentInput::Decay,
             InstrumentInput::Decay => InstrumentInput::Sustain,
@@ -346,13 +1150,31 @@ impl Tracker {
             InstrumentInput::Release => InstrumentInput::Volume,
             InstrumentInput::Volume => InstrumentInput::Peak,
             InstrumentInput::Peak => InstrumentInput::NoteSweep,
-            InstrumentInput::NoteSweep => InstrumentInput::NoteSweep,
+            InstrumentInput::NoteSweep => InstrumentInput::Glide,
+            InstrumentInput::Glide => InstrumentInput::Finetune,
+            InstrumentInput::Finetune => InstrumentInput::Pan,
+            InstrumentInput::Pan => InstrumentInput::DrumKit,
+            InstrumentInput::DrumKit => InstrumentInput::DrumKey,
+            InstrumentInput::DrumKey => InstrumentInput::Drum,
+            InstrumentInput::Drum => InstrumentInput::KitFrequency,
+            InstrumentInput::KitFrequency => InstrumentInput::KitSweep,
+            InstrumentInput::KitSweep => InstrumentInput::KitEnvelope,
+            InstrumentInput::KitEnvelope => InstrumentInput::KitVolume,
+            InstrumentInput::KitVolume => InstrumentInput::VibratoDepth,
+            InstrumentInput::VibratoDepth => InstrumentInput::VibratoSpeed,
+            InstrumentInput::VibratoSpeed => InstrumentInput::VibratoDelay,
+            InstrumentInput::VibratoDelay => InstrumentInput::TremoloDepth,
+            InstrumentInput::TremoloDepth => InstrumentInput::TremoloSpeed,
+            InstrumentInput::TremoloSpeed => InstrumentInput::TremoloDelay,
+            InstrumentInput::TremoloDelay => InstrumentInput::Preset,
+            InstrumentInput::Preset => InstrumentInput::Preset,
         }
     }
 
     pub fn instrument_focus_prev(&mut self) {
         self.instrument_focus = match self.instrument_focus {
-            InstrumentInput::DutyCycle => InstrumentInput::DutyCycle,
+            InstrumentInput::Name => InstrumentInput::Name,
+            InstrumentInput::DutyCycle => InstrumentInput::Name,
             InstrumentInput::Attack => InstrumentInput::DutyCycle,
             InstrumentInput::Decay => InstrumentInput::Attack,
             InstrumentInput::Sustain => InstrumentInput::Decay,
@@ -360,6 +1182,91 @@ impl Tracker {
             InstrumentInput::Volume => InstrumentInput::Release,
             InstrumentInput::Peak => InstrumentInput::Volume,
             InstrumentInput::NoteSweep => InstrumentInput::Peak,
+            InstrumentInput::Glide => InstrumentInput::NoteSweep,
+            InstrumentInput::Finetune => InstrumentInput::Glide,
+            InstrumentInput::Pan => InstrumentInput::Finetune,
+            InstrumentInput::DrumKit => InstrumentInput::Pan,
+            InstrumentInput::DrumKey => InstrumentInput::DrumKit,
+            InstrumentInput::Drum => InstrumentInput::DrumKey,
+            InstrumentInput::KitFrequency => InstrumentInput::Drum,
+            InstrumentInput::KitSweep => InstrumentInput::KitFrequency,
+            InstrumentInput::KitEnvelope => InstrumentInput::KitSweep,
+            InstrumentInput::KitVolume => InstrumentInput::KitEnvelope,
+            InstrumentInput::VibratoDepth => InstrumentInput::KitVolume,
+            InstrumentInput::VibratoSpeed => InstrumentInput::VibratoDepth,
+            InstrumentInput::VibratoDelay => InstrumentInput::VibratoSpeed,
+            InstrumentInput::TremoloDepth => InstrumentInput::VibratoDelay,
+            InstrumentInput::TremoloSpeed => InstrumentInput::TremoloDepth,
+            InstrumentInput::TremoloDelay => InstrumentInput::TremoloSpeed,
+            InstrumentInput::Preset => InstrumentInput::TremoloDelay,
+        }
+    }
+
+    pub fn instrument_name_cursor(&self) -> usize {
+        self.instrument_name_cursor
+    }
+
+    pub fn next_instrument_name_cursor(&mut self) {
+        if self.instrument_name_cursor < INSTRUMENT_NAME_LENGTH - 1 {
+            self.instrument_name_cursor += 1
+        }
+    }
+
+    pub fn prev_instrument_name_cursor(&mut self) {
+        if self.instrument_name_cursor != 0 {
+            self.instrument_name_cursor -= 1
+        }
+    }
+
+    pub fn macro_cursor_kind(&self) -> MacroKind {
+        self.macro_cursor_kind
+    }
+
+    pub fn next_macro_cursor_kind(&mut self) {
+        self.macro_cursor_kind = self.macro_cursor_kind.next()
+    }
+
+    pub fn prev_macro_cursor_kind(&mut self) {
+        self.macro_cursor_kind = self.macro_cursor_kind.prev()
+    }
+
+    pub fn macro_cursor_step(&self) -> usize {
+        self.macro_cursor_step
+    }
+
+    pub fn set_macro_cursor(&mut self, kind: MacroKind, step: usize) {
+        self.macro_cursor_kind = kind;
+        self.macro_cursor_step = step.min(MAX_SEQUENCE_LENGTH - 1)
+    }
+
+    pub fn next_macro_cursor_step(&mut self) {
+        if self.macro_cursor_step < MAX_SEQUENCE_LENGTH - 1 {
+            self.macro_cursor_step += 1
+        }
+    }
+
+    pub fn prev_macro_cursor_step(&mut self) {
+        if self.macro_cursor_step != 0 {
+            self.macro_cursor_step -= 1
+        }
+    }
+
+    pub fn notice(&self) -> Option<&'static [u8]> {
+        self.notice.map(|(notice, _)| notice)
+    }
+
+    // A new notice replaces the shown one and stays up for the full time
+    pub fn notify(&mut self, notice: &'static [u8]) {
+        unsafe {
+            if let Some((_, timeout_id)) = self.notice {
+                timers().cancel(timeout_id);
+            }
+            let timeout_id = timers().set_timeout(
+                get_unique_usize(),
+                || tracker().notice = None,
+                NOTICE_FRAMES,
+            );
+            self.notice = Some((notice, timeout_id));
         }
     }
 
@@ -368,24 +1275,18 @@ impl Tracker {
     }
 
     pub fn current_note(&self) -> &Option<Note> {
-        &self.patterns[self.selected_pattern][self.cursor_tick as usize]
+        &self.patterns[self.selected_pattern][self.cursor_tick() as usize]
     }
 
     pub fn current_note_mut(&mut self) -> &mut Option<Note> {
-        &mut self.patterns[self.selected_pattern][self.cursor_tick as usize]
+        let tick = self.cursor_tick() as usize;
+        &mut self.patterns[self.selected_pattern][tick]
     }
 
     pub fn set_current_note(&mut self, note: &Option<Note>) {
-        self.patterns[self.selected_pattern][self.cursor_tick as usize] = *note
-    }
-
-    pub fn update_current_note<F>(&mut self, f: F)
-    where
-        F: FnOnce(&Option<Note>) -> &Option<Note>,
-    {
-        let current_note = self.current_note().clone();
-        let new_note = f(&current_note);
-        self.set_current_note(new_note)
+        let before = *self.current_note();
+        *self.current_note_mut() = *note;
+        self.fit_player_note(0, before);
     }
 
     pub fn note_at(&self, index: usize) -> Option<Note> {
@@ -394,22 +1295,49 @@ impl Tracker {
             .and_then(|a| *a)
     }
 
-    pub fn selected_channel(&self) -> &Channel {
-        &self.selected_channel
+    pub fn selected_channel(&self) -> Channel {
+        self.players[0].channel()
     }
 
-    pub fn next_channel(&mut self) {
-        self.selected_channel = self.selected_channel.next()
+    pub fn song_column(&self) -> SongColumn {
+        self.song_column
     }
 
-    pub fn prev_channel(&mut self) {
-        self.selected_channel = self.selected_channel.prev()
+    // Moves through the pattern and transpose columns of every channel
+    pub fn next_song_column(&mut self) {
+        match self.song_column {
+            SongColumn::Pattern => self.song_column = SongColumn::Transpose,
+            SongColumn::Transpose => {
+                if self.selected_channel() != Channel::Noise {
+                    self.players[0].set_channel(self.selected_channel().next());
+                    self.song_column = SongColumn::Pattern;
+                }
+            }
+        }
+    }
+
+    pub fn prev_song_column(&mut self) {
+        match self.song_column {
+            SongColumn::Transpose => self.song_column = SongColumn::Pattern,
+            SongColumn::Pattern => {
+                if self.selected_channel() != Channel::Pulse1 {
+                    self.players[0].set_channel(self.selected_channel().prev());
+                    self.song_column = SongColumn::Transpose;
+                }
+            }
+        }
     }
 
     pub fn song_cursor_row(&self) -> usize {
         self.song_cursor_row_index
     }
 
+    pub fn set_song_cursor(&mut self, row: usize, channel: Channel, column: SongColumn) {
+        self.song_cursor_row_index = row.min(SONG_SIZE - 1);
+        self.players[0].set_channel(channel);
+        self.song_column = column;
+    }
+
     pub fn next_row_song_cursor(&mut self) {
         const LAST_TO_MOVE: usize = SONG_SIZE - 2;
         self.song_cursor_row_index = match self.song_cursor_row_index {
@@ -421,7 +1349,7 @@ impl Tracker {
     pub fn prev_row_song_cursor(&mut self) {
         self.song_cursor_row_index = match self.song_cursor_row_index {
             0 => 0,
-            x @ _ => x - 1,
+            x => x - 1,
         }
     }
 
@@ -445,157 +1373,558 @@ impl Tracker {
         &self.play
     }
 
+    // Position in the song or the pattern being played, None when stopped
+    pub fn play_position(&self) -> Option<Duration> {
+        let ticks = match self.play {
+            PlayMode::Idle => return None,
+            PlayMode::Song => self.song_tick as u32 * 16 + self.tick as u32,
+            PlayMode::Pattern => self.tick as u32,
+        };
+        Some(Duration::from_frames(ticks * FRAMES_PER_TICK + self.frame))
+    }
+
     pub fn song_tick(&self) -> usize {
         self.song_tick
     }
 
+    pub fn is_pattern_used(&self, index: usize) -> bool {
+        self.pattern_usage_count(index) > 0
+    }
+
+    pub fn pattern_usage_count(&self, index: usize) -> usize {
+        self.song
+            .iter()
+            .flat_map(|row| Channel::iterator().map(|channel| *row.channel(&channel)))
+            .filter(|pattern_index| *pattern_index == Some(index))
+            .count()
+    }
+
+    pub fn pattern(&self, index: usize) -> &[Option<Note>; 16] {
+        &self.patterns[index]
+    }
+
+    pub fn is_pattern_empty(&self, index: usize) -> bool {
+        self.patterns[index].iter().all(|note| note.is_none())
+    }
+
+    pub fn first_free_pattern(&self) -> Option<usize> {
+        (0..MAX_PATTERNS)
+            .find(|&index| !self.is_pattern_used(index) && self.is_pattern_empty(index))
+    }
+
+    pub fn clone_pattern(&mut self, index: usize) -> Option<usize> {
+        let free_index = self.first_free_pattern()?;
+        self.patterns[free_index] = self.patterns[index];
+        if !self.fits_on_disk() {
+            self.clear_pattern(free_index);
+            self.notify(DISK_FULL_NOTICE);
+            return None;
+        }
+        Some(free_index)
+    }
+
+    pub fn clear_pattern(&mut self, index: usize) {
+        self.patterns[index] = [None; 16];
+    }
+
+    // The pattern open in the editor is kept even when the song doesn't use it
+    pub fn clear_unused_patterns(&mut self) {
+        for index in 0..MAX_PATTERNS {
+            if !self.is_pattern_used(index) && index != self.selected_pattern {
+                self.clear_pattern(index);
+            }
+        }
+    }
+
+    pub fn next_selected_pattern(&mut self) {
+        if self.selected_pattern < MAX_PATTERNS - 1 {
+            self.selected_pattern += 1
+        }
+    }
+
+    pub fn prev_selected_pattern(&mut self) {
+        if self.selected_pattern != 0 {
+            self.selected_pattern -= 1
+        }
+    }
+
     pub fn persist(&self) {
-        let layout_version_section_size: usize = 1;
-        let song_section_size = self.song.len() * 4;
-        let instrumens_section_size = self.instruments.len() * size_of::<Instrument>();
-        let patterns_section_size = self.patterns.len() * 16 * 2;
-        let stored_size = layout_version_section_size
-            + song_section_size
-            + instrumens_section_size
-            + patterns_section_size;
-
-        let mut buf = vec![0_u8; stored_size];
-        let mut next_byte: usize = 0;
+        let buf = self.save_bytes();
+        unsafe {
+            diskw(buf.as_ptr(), buf.len() as u32);
+        }
+    }
+
+    // Edits that would make this larger than the disk are undone, so saving never fails
+    fn save_bytes(&self) -> Vec<u8> {
+        let mut buf: Vec<u8> = vec![];
 
         // storage version (1)
-        buf[0] = STORAGE_LAYOUT_VERSION;
-        next_byte += 1;
+        buf.push(STORAGE_LAYOUT_VERSION);
 
         // song (song.len()*4)
         for row in self.song {
-            let row_bytes = row.to_bytes(STORAGE_LAYOUT_VERSION);
-            for byte in row_bytes {
-                buf[next_byte] = byte;
-                next_byte += 1;
-            }
+            buf.extend_from_slice(&row.to_bytes(STORAGE_LAYOUT_VERSION));
         }
 
-        // instruments (MAX_INSTRUMENTS * 5)
-        for instrument in self.instruments {
+        // instruments (MAX_INSTRUMENTS * 8)
+        for instrument in &self.instruments {
             let instrument_bytes = instrument.to_bytes(STORAGE_LAYOUT_VERSION);
-            buf[next_byte + 0] = instrument_bytes.0;
-            buf[next_byte + 1] = instrument_bytes.1;
-            buf[next_byte + 2] = instrument_bytes.2;
-            buf[next_byte + 3] = instrument_bytes.3;
-            buf[next_byte + 4] = instrument_bytes.4;
-            buf[next_byte + 5] = instrument_bytes.5;
-            buf[next_byte + 6] = instrument_bytes.6;
-            buf[next_byte + 7]  = instrument_bytes.7 as u8;
-            next_byte += 8;
-        }
-
-        // patterns (MAX_PATTERNS * 16 * 2 (note size))
+            buf.extend_from_slice(&[
+                instrument_bytes.0,
+                instrument_bytes.1,
+                instrument_bytes.2,
+                instrument_bytes.3,
+                instrument_bytes.4,
+                instrument_bytes.5,
+                instrument_bytes.6,
+                instrument_bytes.7 as u8,
+            ]);
+        }
+
+        // patterns (MAX_PATTERNS * (2 (steps mask) + steps count * 2 (note size)))
         for pattern in &self.patterns {
-            for note in pattern {
-                let bytes = match note {
-                    Some(note) => note.to_bytes(),
-                    None => (0xff, 0xff),
-                };
-                buf[next_byte] = bytes.0;
-                buf[next_byte + 1] = bytes.1;
-                next_byte += 2;
+            let mask_position = buf.len();
+            buf.extend_from_slice(&[0, 0]);
+            let mut mask = 0_u16;
+            for (step, note) in pattern.iter().enumerate() {
+                if let Some(note) = note {
+                    mask |= 1 << step;
+                    let bytes = note.to_bytes();
+                    buf.extend_from_slice(&[bytes.0, bytes.1]);
+                }
             }
+            buf[mask_position..mask_position + 2].copy_from_slice(&mask.to_be_bytes());
         }
 
-        unsafe {
-            diskw(addr_of!(buf.as_slice()[0]), stored_size as u32);
+        // instrument names (4 (named instruments mask) + named count * 6 chars * 6 bits)
+        let mut mask = 0_u32;
+        let mut name_codes = vec![];
+        for (index, instrument) in self.instruments.iter().enumerate() {
+            if instrument.has_name() {
+                mask |= 1 << index;
+                name_codes.extend_from_slice(&instrument.name_codes());
+            }
+        }
+        buf.extend_from_slice(&mask.to_be_bytes());
+        buf.extend_from_slice(&pack_bits(&name_codes, NAME_CHAR_BITS as u32));
+
+        // macros (1 (count) + count * (1 (instrument and kind) + 3 (header) + length),
+        // MACRO_SECTION_SIZE at most)
+        let count_position = buf.len();
+        buf.push(0);
+        for (index, instrument) in self.instruments.iter().enumerate() {
+            for kind in MacroKind::iterator() {
+                let sequence = instrument.macro_sequence(kind);
+                if !sequence.is_empty() {
+                    buf[count_position] += 1;
+                    buf.push((index as u8) << 2 | kind.index() as u8);
+                    buf.extend_from_slice(&sequence.to_bytes());
+                }
+            }
+        }
+
+        // LFOs (1 (count) + count * (1 (instrument) + 2 (vibrato) + 2 (tremolo)))
+        let count_position = buf.len();
+        buf.push(0);
+        for (index, instrument) in self.instruments.iter().enumerate() {
+            if *instrument.vibrato() != Lfo::off() || *instrument.tremolo() != Lfo::off() {
+                buf[count_position] += 1;
+                buf.push(index as u8);
+                buf.extend_from_slice(&instrument.vibrato().to_bytes());
+                buf.extend_from_slice(&instrument.tremolo().to_bytes());
+            }
         }
+
+        // tuning (2 (A4) + 1 (temperament) + 12 (custom table))
+        buf.extend_from_slice(&self.tuning.to_bytes());
+
+        // fine tunes (1 (count) + count * (1 (instrument) + 1 (cents)))
+        let count_position = buf.len();
+        buf.push(0);
+        for (index, instrument) in self.instruments.iter().enumerate() {
+            if instrument.finetune() != 0 {
+                buf[count_position] += 1;
+                buf.extend_from_slice(&[index as u8, instrument.finetune() as u8]);
+            }
+        }
+
+        // chords (2 (patterns mask) + patterns with chords * (2 (steps mask) + chords count))
+        let mut patterns_mask = 0_u16;
+        let mut chords = vec![];
+        for (index, pattern) in self.patterns.iter().enumerate() {
+            let mut mask = 0_u16;
+            let mut pattern_chords = vec![];
+            for (step, note) in pattern.iter().enumerate() {
+                if let Some(note) = note.filter(|a| a.has_chord()) {
+                    mask |= 1 << step;
+                    pattern_chords.push(note.chord());
+                }
+            }
+            if mask != 0 {
+                patterns_mask |= 1 << index;
+                chords.extend_from_slice(&mask.to_be_bytes());
+                chords.extend_from_slice(&pattern_chords);
+            }
+        }
+        buf.extend_from_slice(&patterns_mask.to_be_bytes());
+        buf.extend_from_slice(&chords);
+
+        // song transposes (song.len() * 4 * 5 bits)
+        let mut transposes = vec![];
+        for row in &self.song {
+            for transpose in row.transpose {
+                transposes.push(transpose as u8);
+            }
+        }
+        buf.extend_from_slice(&pack_bits(&transposes, TRANSPOSE_BITS));
+
+        // echo (3)
+        buf.extend_from_slice(&self.echo.to_bytes());
+
+        // bindings (4)
+        buf.extend_from_slice(&self.bindings.to_bytes());
+
+        // key and scale (1)
+        buf.push(self.key | self.scale.to_bits() << 4);
+
+        // preview off (1)
+        buf.push(!self.preview as u8);
+
+        // drum kits (1 (count) + count * (1 (instrument) + 16 keys * 4 bits + 4 (params))),
+        // only the ones changed from the default kit
+        let count_position = buf.len();
+        buf.push(0);
+        for (index, instrument) in self.instruments.iter().enumerate() {
+            if *instrument.drums() != DEFAULT_DRUM_KIT
+                || *instrument.kit_params() != KitParams::new()
+            {
+                buf[count_position] += 1;
+                buf.push(index as u8);
+                buf.extend_from_slice(&pack_bits(instrument.drums(), DRUM_BITS));
+                buf.extend_from_slice(&instrument.kit_params().to_bytes());
+            }
+        }
+
+        buf
+    }
+
+    fn fits_on_disk(&self) -> bool {
+        self.save_bytes().len() <= DISK_SIZE
     }
 
     pub fn restore() -> Tracker {
         let mut tracker = Tracker::new();
 
-        let mut buf = [0u8; 1
-            + SONG_SIZE * 4
-            + MAX_INSTRUMENTS * size_of::<Instrument>()
-            + MAX_PATTERNS * 16 * 2];
+        let mut buf = [0u8; DISK_SIZE];
 
         unsafe {
             diskr(buf.as_mut_ptr(), buf.len() as u32);
         }
 
-        let mut next_byte: usize = 0;
+        let mut reader = ByteReader::new(&buf);
 
         // storage version (1)
-        let version = buf[next_byte];
-        if version != STORAGE_LAYOUT_VERSION {
+        let version = reader.u8();
+        if version != 1 && version != STORAGE_LAYOUT_VERSION {
             return tracker;
         }
-        next_byte += 1;
 
         // song (4*4)
         for row_index in 0..SONG_SIZE {
-            let pulse1: Option<usize> = if let 255 = buf[next_byte + 0] {
-                None
-            } else {
-                Some(buf[next_byte + 0].into())
-            };
-            let pulse2: Option<usize> = if let 255 = buf[next_byte + 1] {
-                None
-            } else {
-                Some(buf[next_byte + 1].into())
-            };
-            let triangle: Option<usize> = if let 255 = buf[next_byte + 2] {
-                None
-            } else {
-                Some(buf[next_byte + 2].into())
+            let mut channel_value = || match reader.u8() {
+                255 => None,
+                value => Some(value.into()),
             };
-            let noise: Option<usize> = if let 255 = buf[next_byte + 3] {
-                None
-            } else {
-                Some(buf[next_byte + 3].into())
-            };
-            next_byte += 4;
-
             let row = Row {
-                pulse1,
-                pulse2,
-                triangle,
-                noise,
+                pulse1: channel_value(),
+                pulse2: channel_value(),
+                triangle: channel_value(),
+                noise: channel_value(),
+                transpose: [0; 4],
             };
             tracker.song[row_index] = row;
         }
 
-        // instruments (MAX_INSTRUMENTS * 5)
+        // instruments (MAX_INSTRUMENTS * 8)
         for instrument_index in 0..MAX_INSTRUMENTS {
             let bytes = (
-                buf[next_byte + 0],
-                buf[next_byte + 1],
-                buf[next_byte + 2],
-                buf[next_byte + 3],
-                buf[next_byte + 4],
-                buf[next_byte + 5],
-                buf[next_byte + 6],
-                buf[next_byte + 7] as i8,
+                reader.u8(),
+                reader.u8(),
+                reader.u8(),
+                reader.u8(),
+                reader.u8(),
+                reader.u8(),
+                reader.u8(),
+                reader.u8() as i8,
             );
-            next_byte += 8;
             let instrument = Instrument::from_bytes(bytes);
             tracker.instruments[instrument_index] = instrument;
         }
 
-        // patterns (MAX_PATTERNS * 16 * 2 (note size))
+        // patterns, version 1 stored every step with 0xffff for empty ones
         for pattern_index in 0..MAX_PATTERNS {
+            let mask = match version {
+                1 => 0xffff,
+                _ => reader.u16(),
+            };
             for note_index in 0..16 {
-                let bytes = (buf[next_byte + 0], buf[next_byte + 1]);
-                next_byte += 2;
-                let note = match bytes {
+                if mask & 1 << note_index == 0 {
+                    continue;
+                }
+                let note = match (reader.u8(), reader.u8()) {
                     (0xff, 0xff) => None,
-                    (index, instrument) => Some(Note {
-                        index: index.into(),
-                        instrument: instrument.into(),
-                    }),
+                    bytes => Some(Note::from_bytes(bytes)),
                 };
                 tracker.patterns[pattern_index][note_index] = note;
             }
         }
 
+        // instrument names
+        let mask = u32::from_be_bytes([reader.u8(), reader.u8(), reader.u8(), reader.u8()]);
+        let codes_count = mask.count_ones() as usize * INSTRUMENT_NAME_LENGTH;
+        let name_codes = unpack_bits(
+            reader.bytes(packed_size(codes_count, NAME_CHAR_BITS)),
+            NAME_CHAR_BITS as u32,
+            codes_count,
+        );
+        let named = tracker
+            .instruments
+            .iter_mut()
+            .enumerate()
+            .filter(|(index, _)| mask & 1 << index != 0);
+        for ((_, instrument), codes) in named.zip(name_codes.chunks(INSTRUMENT_NAME_LENGTH)) {
+            instrument.set_name_codes(codes);
+        }
+
+        // macros
+        let macros_count = reader.u8();
+        for _ in 0..macros_count {
+            let instrument_and_kind = reader.u8();
+            let header = (reader.u8(), reader.u8(), reader.u8());
+            let sequence = Sequence::from_bytes(header, reader.bytes(header.0 as usize));
+            let kind = MacroKind::from_index((instrument_and_kind & 0b11) as usize);
+            if let Some(instrument) = tracker
+                .instruments
+                .get_mut((instrument_and_kind >> 2) as usize)
+            {
+                instrument.update_macro(kind, |a| *a = sequence);
+            }
+        }
+
+        // LFOs
+        let lfos_count = reader.u8();
+        for _ in 0..lfos_count {
+            let index = reader.u8() as usize;
+            let vibrato = Lfo::from_bytes([reader.u8(), reader.u8()]);
+            let tremolo = Lfo::from_bytes([reader.u8(), reader.u8()]);
+            if let Some(instrument) = tracker.instruments.get_mut(index) {
+                instrument.set_lfos(vibrato, tremolo);
+            }
+        }
+
+        // tuning
+        let a4 = [reader.u8(), reader.u8()];
+        let temperament = reader.u8();
+        tracker.tuning =
+            Tuning::from_bytes(a4, temperament, reader.bytes(NOTES_PER_OCTAVE as usize));
+
+        // fine tunes
+        let finetunes_count = reader.u8();
+        for _ in 0..finetunes_count {
+            let index = reader.u8() as usize;
+            let cents = reader.u8() as i8;
+            if let Some(instrument) = tracker.instruments.get_mut(index) {
+                instrument.update_finetune(|_| cents);
+            }
+        }
+
+        // chords
+        let patterns_mask = reader.u16();
+        for (index, pattern) in tracker.patterns.iter_mut().enumerate() {
+            if patterns_mask & 1 << index == 0 {
+                continue;
+            }
+            let mask = reader.u16();
+            for (step, note) in pattern.iter_mut().enumerate() {
+                if mask & 1 << step == 0 {
+                    continue;
+                }
+                let chord = reader.u8();
+                if let Some(note) = note {
+                    note.chord = chord;
+                }
+            }
+        }
+
+        // song transposes
+        let transposes = unpack_bits(
+            reader.bytes(packed_size(SONG_SIZE * 4, TRANSPOSE_BITS as usize)),
+            TRANSPOSE_BITS,
+            SONG_SIZE * 4,
+        );
+        for (row, transposes) in tracker.song.iter_mut().zip(transposes.chunks(4)) {
+            for (transpose, &bits) in row.transpose.iter_mut().zip(transposes) {
+                // sign extend from 5 bits
+                *transpose = ((bits << 3) as i8) >> 3;
+            }
+        }
+
+        // echo
+        tracker.echo = Echo::from_bytes([reader.u8(), reader.u8(), reader.u8()]);
+
+        // bindings
+        tracker.bindings =
+            Bindings::from_bytes([reader.u8(), reader.u8(), reader.u8(), reader.u8()]);
+
+        // key and scale
+        let key_and_scale = reader.u8();
+        tracker.update_key(|_| key_and_scale & 0x0f);
+        tracker.scale = Scale::from_bits(key_and_scale >> 4);
+
+        // preview off
+        tracker.preview = reader.u8() == 0;
+
+        // drum kits
+        let kits_count = reader.u8();
+        for _ in 0..kits_count {
+            let index = reader.u8() as usize;
+            let drums = unpack_bits(
+                reader.bytes(packed_size(DRUM_KIT_SIZE, DRUM_BITS as usize)),
+                DRUM_BITS,
+                DRUM_KIT_SIZE,
+            );
+            let params =
+                KitParams::from_bytes([reader.u8(), reader.u8(), reader.u8(), reader.u8()]);
+            if let Some(instrument) = tracker.instruments.get_mut(index) {
+                instrument.set_drums(&drums);
+                instrument.update_kit_params(|kit| *kit = params);
+            }
+        }
+
         tracker
     }
 }
 
 pub static mut TRACKER: Tracker = Tracker::empty();
+
+pub unsafe fn tracker() -> &'static mut Tracker {
+    &mut *addr_of_mut!(TRACKER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wasm4::DISK;
+
+    // A save from before the patterns were stored sparsely: every step of every
+    // pattern takes two bytes, 0xffff for an empty one, and nothing follows them
+    fn version_1_save() -> Vec<u8> {
+        let mut buf = vec![1];
+        let mut song = [255; SONG_SIZE * 4];
+        song[..4].copy_from_slice(&[0, 255, 1, 255]);
+        buf.extend_from_slice(&song);
+        let mut instruments = [0; MAX_INSTRUMENTS * 8];
+        instruments[..8].copy_from_slice(&[2, 1, 2, 3, 4, 0x40, 0x50, -2_i8 as u8]);
+        buf.extend_from_slice(&instruments);
+        let mut patterns = [0xff; MAX_PATTERNS * 16 * 2];
+        patterns[32..34].copy_from_slice(&[48, 0]);
+        patterns[62..64].copy_from_slice(&[50, 3]);
+        buf.extend_from_slice(&patterns);
+        buf
+    }
+
+    #[test]
+    fn restores_a_version_1_save() {
+        DISK.set(version_1_save());
+        let tracker = Tracker::restore();
+
+        let row = tracker.song[0];
+        assert_eq!(
+            (row.pulse1, row.pulse2, row.triangle),
+            (Some(0), None, Some(1))
+        );
+        assert_eq!(tracker.song[1].noise, None);
+
+        let instrument = tracker.instruments[0];
+        assert!(matches!(instrument.duty_cycle(), DutyCycle::Half));
+        assert_eq!(
+            (
+                instrument.attack(),
+                instrument.decay(),
+                instrument.sustain()
+            ),
+            (1, 2, 4)
+        );
+        assert_eq!(instrument.release(), 3);
+        assert_eq!((instrument.volume(), instrument.peak()), (0x40, 0x50));
+        assert_eq!(instrument.note_sweep(), -2);
+
+        let pattern = tracker.patterns[1];
+        assert_eq!(pattern[0].map(|a| (a.index, a.instrument)), Some((48, 0)));
+        assert!(pattern[1..15].iter().all(|a| a.is_none()));
+        assert_eq!(pattern[15].map(|a| (a.index, a.instrument)), Some((50, 3)));
+        assert!(tracker.patterns[0].iter().all(|a| a.is_none()));
+    }
+
+    #[test]
+    fn restores_defaults_for_sections_newer_than_version_1() {
+        DISK.set(version_1_save());
+        let tracker = Tracker::restore();
+        assert!(!tracker.instruments[0].has_name());
+        assert!(tracker.instruments[0]
+            .macro_sequence(MacroKind::Volume)
+            .is_empty());
+        assert_eq!(tracker.tuning.to_bytes(), Tuning::new().to_bytes());
+        assert_eq!(tracker.echo.to_bytes(), Echo::new().to_bytes());
+        assert_eq!(tracker.bindings.to_bytes(), Bindings::new().to_bytes());
+    }
+
+    #[test]
+    fn ignores_saves_of_unknown_versions() {
+        let mut buf = version_1_save();
+        buf[0] = STORAGE_LAYOUT_VERSION + 1;
+        DISK.set(buf);
+        assert_eq!(
+            Tracker::restore().song[0].pulse1,
+            Tracker::new().song[0].pulse1
+        );
+    }
+
+    // Every step of every pattern with a chord is more than the disk holds
+    fn fill_patterns_with_chords(tracker: &mut Tracker) {
+        let mut note = Note::new();
+        note.update_chord_first(|_| 4);
+        for pattern in 0..MAX_PATTERNS {
+            tracker.set_selected_pattern(pattern);
+            for tick in 0..16 {
+                tracker.set_cursor_tick(tick);
+                tracker.set_current_note(&Some(note));
+            }
+        }
+    }
+
+    #[test]
+    fn undoes_edits_that_would_not_fit_on_the_disk() {
+        let mut tracker = Tracker::new();
+        fill_patterns_with_chords(&mut tracker);
+        assert!(tracker.notice() == Some(DISK_FULL_NOTICE));
+        assert!(tracker.save_bytes().len() <= DISK_SIZE);
+        assert!(tracker.patterns[MAX_PATTERNS - 1][15].is_none());
+
+        // A changed drum kit takes 13 bytes
+        tracker
+            .selected_instrument_mut()
+            .update_kit_params(|kit| kit.update_volume(|_| 50));
+        tracker.fit_selected_instrument(DEFAULT_INSTRUMENT);
+        assert!(*tracker.selected_instrument().kit_params() == KitParams::new());
+    }
+
+    #[test]
+    fn restores_a_full_save() {
+        let mut tracker = Tracker::new();
+        fill_patterns_with_chords(&mut tracker);
+        tracker.persist();
+        assert_eq!(Tracker::restore().save_bytes(), tracker.save_bytes());
+    }
+}
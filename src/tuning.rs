@@ -0,0 +1,240 @@
+use crate::notes::NOTES_PER_OCTAVE;
+
+// A4 is kept in tenths of a hertz
+pub const DEFAULT_A4: u16 = 4400;
+const MIN_A4: u16 = 4000;
+const MAX_A4: u16 = 4800;
+
+const A4_INDEX: usize = 57;
+
+pub const MAX_CENTS: i8 = 50;
+
+// Cents away from equal temperament of every pitch class, just intonation over C
+const JUST_INTONATION_CENTS: [i8; 12] = [0, 12, 4, 16, -14, -2, -10, 2, 14, -16, 18, -12];
+
+const CENTS_PER_OCTAVE: i32 = 1200;
+
+// Frequency ratios in 16.16 fixed point, of every semitone of an octave and of
+// every 10 cents of a semitone
+const SEMITONE_RATIOS: [u64; 12] = [
+    65536, 69433, 73562, 77936, 82570, 87480, 92682, 98193, 104032, 110218, 116772, 123715,
+];
+const CENT_RATIOS: [u64; 11] = [
+    65536, 65916, 66297, 66682, 67068, 67456, 67847, 68240, 68635, 69033, 69433,
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Temperament {
+    Equal,
+    Just,
+    Custom,
+}
+
+impl Temperament {
+    pub fn next(&self) -> Self {
+        match self {
+            Temperament::Equal => Temperament::Just,
+            Temperament::Just => Temperament::Custom,
+            &custom @ Temperament::Custom => custom,
+        }
+    }
+
+    pub fn prev(&self) -> Self {
+        match self {
+            &equal @ Temperament::Equal => equal,
+            Temperament::Just => Temperament::Equal,
+            Temperament::Custom => Temperament::Just,
+        }
+    }
+
+    pub fn to_render(self) -> &'static str {
+        match self {
+            Temperament::Equal => "EQUAL",
+            Temperament::Just => "JUST",
+            Temperament::Custom => "CUSTOM",
+        }
+    }
+
+    pub fn to_bits(self) -> u8 {
+        match self {
+            Temperament::Equal => 0,
+            Temperament::Just => 1,
+            Temperament::Custom => 2,
+        }
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        match bits {
+            1 => Temperament::Just,
+            2 => Temperament::Custom,
+            _ => Temperament::Equal,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Tuning {
+    a4: u16,
+    temperament: Temperament,
+    custom_cents: [i8; NOTES_PER_OCTAVE as usize],
+}
+
+impl Tuning {
+    pub const fn new() -> Self {
+        Tuning {
+            a4: DEFAULT_A4,
+            temperament: Temperament::Equal,
+            custom_cents: [0; NOTES_PER_OCTAVE as usize],
+        }
+    }
+
+    pub fn a4(&self) -> u16 {
+        self.a4
+    }
+
+    pub fn temperament(&self) -> Temperament {
+        self.temperament
+    }
+
+    pub fn custom_cents(&self, pitch_class: usize) -> i8 {
+        self.custom_cents[pitch_class]
+    }
+
+    pub fn update_a4<F>(&mut self, f: F)
+    where
+        F: FnOnce(u16) -> u16,
+    {
+        self.a4 = f(self.a4).clamp(MIN_A4, MAX_A4)
+    }
+
+    pub fn update_temperament<F>(&mut self, f: F)
+    where
+        F: FnOnce(Temperament) -> Temperament,
+    {
+        self.temperament = f(self.temperament)
+    }
+
+    pub fn update_custom_cents<F>(&mut self, pitch_class: usize, f: F)
+    where
+        F: FnOnce(i8) -> i8,
+    {
+        if let Some(cents) = self.custom_cents.get_mut(pitch_class) {
+            *cents = f(*cents).clamp(-MAX_CENTS, MAX_CENTS)
+        }
+    }
+
+    fn pitch_class_cents(&self, pitch_class: usize) -> i8 {
+        match self.temperament {
+            Temperament::Equal => 0,
+            Temperament::Just => JUST_INTONATION_CENTS[pitch_class],
+            Temperament::Custom => self.custom_cents[pitch_class],
+        }
+    }
+
+    // Pitch of a note index in NOTE_FREQ order detuned by `cents`, in cents from A4
+    pub fn pitch(&self, note_index: usize, cents: i8) -> i32 {
+        let pitch_class = note_index % NOTES_PER_OCTAVE as usize;
+        (note_index as i32 - A4_INDEX as i32) * 100
+            + self.pitch_class_cents(pitch_class) as i32
+            + cents as i32
+    }
+
+    // Frequency in hertz of a pitch in cents from A4, rounded and at least 1
+    pub fn pitch_frequency(&self, pitch: i32) -> u32 {
+        let octave = pitch.div_euclid(CENTS_PER_OCTAVE);
+        let cents = pitch.rem_euclid(CENTS_PER_OCTAVE) as usize;
+        // Linear between the 10 cent steps
+        let tens = cents % 100 / 10;
+        let low = CENT_RATIOS[tens];
+        let cent_ratio = low + (CENT_RATIOS[tens + 1] - low) * (cents % 10) as u64 / 10;
+        let ratio = (SEMITONE_RATIOS[cents / 100] * cent_ratio) >> 16;
+        // 16.16 hertz, A4 is in tenths
+        let frequency = self.a4 as u64 * ratio / 10;
+        let frequency = if octave < 0 {
+            frequency >> -octave
+        } else {
+            frequency << octave
+        };
+        ((frequency + 0x8000) >> 16).max(1) as u32
+    }
+
+    pub fn frequency(&self, note_index: usize, cents: i8) -> u32 {
+        self.pitch_frequency(self.pitch(note_index, cents))
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = self.a4.to_be_bytes().to_vec();
+        bytes.push(self.temperament.to_bits());
+        bytes.extend(self.custom_cents.iter().map(|&a| a as u8));
+        bytes
+    }
+
+    // Zeroed bytes, as read from older saves, give the default tuning
+    pub fn from_bytes(a4: [u8; 2], temperament: u8, custom_cents: &[u8]) -> Self {
+        let mut tuning = Tuning::new();
+        match u16::from_be_bytes(a4) {
+            0 => {}
+            a4 => tuning.update_a4(|_| a4),
+        }
+        tuning.temperament = Temperament::from_bits(temperament);
+        for (pitch_class, &cents) in custom_cents.iter().enumerate() {
+            tuning.update_custom_cents(pitch_class, |_| cents as i8);
+        }
+        tuning
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_pitches_in_cents_from_a4() {
+        let tuning = Tuning::new();
+        assert_eq!(tuning.pitch(A4_INDEX, 0), 0);
+        assert_eq!(tuning.pitch(A4_INDEX + 12, 0), 1200);
+        assert_eq!(tuning.pitch(A4_INDEX - 1, 0), -100);
+        assert_eq!(tuning.pitch(A4_INDEX, -MAX_CENTS), -50);
+    }
+
+    #[test]
+    fn detunes_pitch_classes_by_the_temperament() {
+        let mut tuning = Tuning::new();
+        tuning.update_temperament(|_| Temperament::Just);
+        // E above C is a pure third, 14 cents below the equal one
+        assert_eq!(tuning.pitch(A4_INDEX - 5, 0), -514);
+        tuning.update_temperament(|_| Temperament::Custom);
+        tuning.update_custom_cents(9, |_| 100);
+        assert_eq!(tuning.pitch(A4_INDEX, 3), MAX_CENTS as i32 + 3);
+    }
+
+    #[test]
+    fn doubles_the_frequency_every_octave() {
+        let mut tuning = Tuning::new();
+        assert_eq!(tuning.pitch_frequency(0), 440);
+        assert_eq!(tuning.pitch_frequency(1200), 880);
+        assert_eq!(tuning.pitch_frequency(-2400), 110);
+        assert_eq!(tuning.pitch_frequency(300), 523);
+        tuning.update_a4(|_| 4320);
+        assert_eq!(tuning.pitch_frequency(-1200), 216);
+    }
+
+    #[test]
+    fn restores_what_was_saved() {
+        let mut tuning = Tuning::new();
+        tuning.update_a4(|_| 4150);
+        tuning.update_temperament(|_| Temperament::Custom);
+        tuning.update_custom_cents(3, |_| -20);
+        let bytes = tuning.to_bytes();
+        let restored = Tuning::from_bytes([bytes[0], bytes[1]], bytes[2], &bytes[3..]);
+        assert_eq!(restored.to_bytes(), bytes);
+        assert_eq!(restored.a4(), 4150);
+        assert_eq!(restored.custom_cents(3), -20);
+    }
+
+    #[test]
+    fn restores_the_default_from_zeros() {
+        let tuning = Tuning::from_bytes([0, 0], 0, &[0; 12]);
+        assert_eq!(tuning.to_bytes(), Tuning::new().to_bytes());
+    }
+}
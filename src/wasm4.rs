@@ -17,6 +17,8 @@ pub const SCREEN_SIZE: u32 = 160;
 // │                                                                           │
 // └───────────────────────────────────────────────────────────────────────────┘
 
+// A fixed address that happens to equal the alignment, not a dangling pointer
+#[allow(clippy::manual_dangling_ptr)]
 pub const PALETTE: *mut [u32; 4] = 0x04 as *mut [u32; 4];
 pub const DRAW_COLORS: *mut u16 = 0x14 as *mut u16;
 pub const GAMEPAD1: *const u8 = 0x16 as *const u8;
@@ -204,6 +206,7 @@ pub const TONE_PAN_RIGHT: u32 = 32;
 // │                                                                           │
 // └───────────────────────────────────────────────────────────────────────────┘
 
+#[cfg(not(test))]
 extern "C" {
     /// Reads up to `size` bytes from persistent storage into the pointer `dest`.
     pub fn diskr(dest: *mut u8, size: u32) -> u32;
@@ -212,6 +215,27 @@ extern "C" {
     pub fn diskw(src: *const u8, size: u32) -> u32;
 }
 
+// Unit tests run on the host, where each test thread gets its own disk
+#[cfg(test)]
+std::thread_local! {
+    pub static DISK: std::cell::RefCell<Vec<u8>> = const { std::cell::RefCell::new(vec![]) };
+}
+
+#[cfg(test)]
+pub unsafe fn diskr(dest: *mut u8, size: u32) -> u32 {
+    DISK.with_borrow(|disk| {
+        let size = disk.len().min(size as usize);
+        std::ptr::copy_nonoverlapping(disk.as_ptr(), dest, size);
+        size as u32
+    })
+}
+
+#[cfg(test)]
+pub unsafe fn diskw(src: *const u8, size: u32) -> u32 {
+    DISK.set(std::slice::from_raw_parts(src, size as usize).to_vec());
+    size
+}
+
 // ┌───────────────────────────────────────────────────────────────────────────┐
 // │                                                                           │
 // │ Other Functions                                                           │
@@ -1,9 +1,17 @@
+use crate::digits::decimal;
 use std::{
+    cmp::Ordering,
     ops::{Add, Sub},
     time::Duration,
 };
 
-#[derive(PartialEq, PartialOrd, Clone, Copy)]
+// WASM-4 calls update 60 times per second
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+// Frames since the cart started, the counter wraps after about two years
+#[derive(PartialEq, Eq, Clone, Copy)]
 pub struct Winstant(u32);
 
 static mut NOW: u32 = 0;
@@ -18,31 +26,101 @@ impl Winstant {
     }
 
     pub fn tick() {
-        unsafe { NOW += 1 }
+        unsafe { NOW = NOW.wrapping_add(1) }
+    }
+
+    // Zero when `earlier` is actually later
+    pub fn frames_since(&self, earlier: Winstant) -> u32 {
+        if *self < earlier {
+            0
+        } else {
+            self.0.wrapping_sub(earlier.0)
+        }
     }
 
     pub fn duration_since(&self, earlier: Winstant) -> Duration {
-        Duration::from_frames(self.0 - earlier.0)
+        Duration::from_frames(self.frames_since(earlier))
     }
 }
 
-const EXPECTED_FRAME_DURATION_MS: u32 = 1000 / 60;
+// Instants less than half the counter range apart compare in the order they
+// happened, also across the wrap
+impl PartialOrd for Winstant {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some((self.0.wrapping_sub(other.0) as i32).cmp(&0))
+    }
+}
 
 impl Add<Duration> for Winstant {
     type Output = Self;
 
     fn add(self, rhs: Duration) -> Self::Output {
-        let add_ms: u32 = rhs.as_millis() as u32 / EXPECTED_FRAME_DURATION_MS;
-        Winstant(self.0 + add_ms)
+        Winstant(self.0.wrapping_add(rhs.as_frames()))
+    }
+}
+
+impl Sub<Winstant> for Winstant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Winstant) -> Self::Output {
+        self.duration_since(rhs)
     }
 }
 
-pub trait FromFrames {
+pub trait Frames {
     fn from_frames(x: u32) -> Self;
+    fn as_frames(&self) -> u32;
 }
 
-impl FromFrames for Duration {
+impl Frames for Duration {
+    // Rounded down to the nanosecond, as_frames gives the same count back
     fn from_frames(x: u32) -> Self {
-        Duration::from_millis((EXPECTED_FRAME_DURATION_MS * x).into())
+        Duration::from_nanos(u64::from(x) * NANOS_PER_SECOND / u64::from(FRAMES_PER_SECOND))
+    }
+
+    // Rounded up, anything longer than zero lasts at least one frame
+    fn as_frames(&self) -> u32 {
+        let seconds = self.as_secs().min(u64::from(u32::MAX));
+        let frames = seconds * u64::from(FRAMES_PER_SECOND)
+            + (u64::from(self.subsec_nanos()) * u64::from(FRAMES_PER_SECOND))
+                .div_ceil(NANOS_PER_SECOND);
+        frames.min(u64::from(u32::MAX)) as u32
+    }
+}
+
+// mm:ss, minutes keep counting past an hour
+pub fn duration_to_render(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    decimal((seconds / 60) as u32, 2) + ":" + &decimal((seconds % 60) as u32, 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_frames_across_the_wrap() {
+        let before = Winstant(u32::MAX - 1);
+        let after = before + Duration::from_frames(3);
+        assert!(after == Winstant(1));
+        assert!(after > before);
+        assert_eq!(after.frames_since(before), 3);
+        assert_eq!(before.frames_since(after), 0);
+        assert_eq!((after - before).as_frames(), 3);
+    }
+
+    #[test]
+    fn converts_frames_back_and_forth() {
+        assert_eq!(Duration::from_frames(1).as_frames(), 1);
+        assert_eq!(Duration::from_frames(61).as_frames(), 61);
+        assert_eq!(Duration::from_millis(300).as_frames(), 18);
+        assert_eq!(Duration::from_millis(1).as_frames(), 1);
+        assert_eq!(Duration::ZERO.as_frames(), 0);
+    }
+
+    #[test]
+    fn renders_minutes_and_seconds() {
+        assert_eq!(duration_to_render(Duration::from_secs(61)), "01:01");
+        assert_eq!(duration_to_render(Duration::from_secs(6000)), "100:00");
     }
 }